@@ -194,6 +194,37 @@ impl ListState {
         state.logical_scroll_top = Some(scroll_top);
     }
 
+    /// Scroll the list by the given distance, where positive values scroll
+    /// towards the end of the list. The scroll position is clamped to the
+    /// items that have been measured so far.
+    pub fn scroll_by(&self, distance: Pixels) {
+        let state = &mut *self.0.borrow_mut();
+        let height = state
+            .last_layout_bounds
+            .map_or(px(0.), |bounds| bounds.size.height);
+        let scroll_top = state.logical_scroll_top();
+
+        let scroll_max = (state.items.summary().height - height).max(px(0.));
+        let new_scroll_top = (state.scroll_top(&scroll_top) + distance)
+            .max(px(0.))
+            .min(scroll_max);
+
+        let mut cursor = state.items.cursor::<ListItemSummary>();
+        cursor.seek(&Height(new_scroll_top), Bias::Right, &());
+        let item_ix = cursor.start().count;
+        let offset_in_item = new_scroll_top - cursor.start().height;
+
+        state.logical_scroll_top = Some(ListOffset {
+            item_ix,
+            offset_in_item,
+        });
+    }
+
+    /// The bounds the list occupied the last time it was painted.
+    pub fn viewport_bounds(&self) -> Bounds<Pixels> {
+        self.0.borrow().last_layout_bounds.unwrap_or_default()
+    }
+
     /// Scroll the list to the given item, such that the item is fully visible.
     pub fn scroll_to_reveal_item(&self, ix: usize) {
         let state = &mut *self.0.borrow_mut();
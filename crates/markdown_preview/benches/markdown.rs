@@ -0,0 +1,94 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use gpui::{TestAppContext, TestDispatcher};
+use markdown_preview::{
+    markdown_parser::parse_markdown,
+    markdown_preview_settings::MarkdownPreviewSettings,
+    markdown_renderer::{render_markdown_block, RenderContext},
+};
+use rand::{rngs::StdRng, SeedableRng};
+use settings::{Settings, SettingsStore};
+use theme::LoadThemes;
+
+/// Documents from this repository, covering prose, settings examples,
+/// code blocks and lists.
+const DOCUMENTS: &[(&str, &str)] = &[
+    (
+        "configuring_zed",
+        include_str!("../../../docs/src/configuring_zed.md"),
+    ),
+    (
+        "building_zed",
+        include_str!("../../../docs/src/developing_zed__building_zed.md"),
+    ),
+    (
+        "theme_importer",
+        include_str!("../../theme_importer/README.md"),
+    ),
+];
+
+/// Documents are repeated until they have at least this many lines,
+/// the size at which slow parsing makes editing with the preview open sluggish.
+const MIN_LINES: usize = 10_000;
+
+fn large_document(document: &str) -> String {
+    let document = format!("{}\n\n", document.trim_end());
+    let lines = document.lines().count();
+    document.repeat((MIN_LINES + lines - 1) / lines)
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_markdown");
+    for (name, document) in DOCUMENTS {
+        let document = large_document(document);
+        group.throughput(Throughput::Bytes(document.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("default", name),
+            &document,
+            |b, document| b.iter(|| parse_markdown(document, None, &[], false, false)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("smart_typography_and_hard_line_breaks", name),
+            &document,
+            |b, document| b.iter(|| parse_markdown(document, None, &[], true, true)),
+        );
+    }
+    group.finish();
+}
+
+/// Builds the elements of every block of a parsed document, as the preview does
+/// for the blocks it shows. Laying out and painting them isn't included.
+fn build_elements(c: &mut Criterion) {
+    let mut cx = TestAppContext::new(TestDispatcher::new(StdRng::seed_from_u64(0)), None);
+    cx.update(|cx| {
+        let settings = SettingsStore::test(cx);
+        cx.set_global(settings);
+        theme::init(LoadThemes::JustBase, cx);
+        MarkdownPreviewSettings::register(cx);
+    });
+    let cx = cx.add_empty_window();
+
+    let mut group = c.benchmark_group("render_markdown_block");
+    for (name, document) in DOCUMENTS {
+        let document = large_document(document);
+        let markdown = parse_markdown(&document, None, &[], false, false);
+        group.throughput(Throughput::Elements(markdown.children.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &markdown,
+            |b, markdown| {
+                b.iter(|| {
+                    cx.update(|cx| {
+                        let mut render_cx = RenderContext::new(None, cx);
+                        for block in &markdown.children {
+                            black_box(render_markdown_block(block, &mut render_cx));
+                        }
+                    })
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, build_elements);
+criterion_main!(benches);
@@ -1,6 +1,15 @@
-use gpui::{px, FontStyle, FontWeight, HighlightStyle, SharedString, UnderlineStyle};
+use gpui::{
+    px, FontStyle, FontWeight, HighlightStyle, ImageData, Pixels, SharedString, Size,
+    StrikethroughStyle, UnderlineStyle,
+};
 use language::HighlightId;
-use std::{ops::Range, path::PathBuf};
+use std::{
+    fmt::Display,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
 
 #[derive(Debug)]
 #[cfg_attr(test, derive(PartialEq))]
@@ -28,6 +37,55 @@ impl ParsedMarkdownElement {
             Self::HorizontalRule(range) => range.clone(),
         }
     }
+
+    /// Moves the element, and everything in it, by `delta` bytes in its source,
+    /// such as when an edit before it has changed the length of the source.
+    pub fn offset_source_ranges(&mut self, delta: isize) {
+        match self {
+            Self::Heading(heading) => {
+                offset_range(&mut heading.source_range, delta);
+                heading.contents.offset_source_ranges(delta);
+            }
+            Self::List(list) => {
+                offset_range(&mut list.source_range, delta);
+                for item in &mut list.children {
+                    for block in &mut item.contents {
+                        block.offset_source_ranges(delta);
+                    }
+                }
+            }
+            Self::Table(table) => {
+                offset_range(&mut table.source_range, delta);
+                for row in std::iter::once(&mut table.header).chain(&mut table.body) {
+                    for cell in &mut row.children {
+                        cell.offset_source_ranges(delta);
+                    }
+                }
+                if let Some(caption) = &mut table.caption {
+                    caption.offset_source_ranges(delta);
+                }
+            }
+            Self::BlockQuote(block_quote) => {
+                offset_range(&mut block_quote.source_range, delta);
+                for block in &mut block_quote.children {
+                    block.offset_source_ranges(delta);
+                }
+            }
+            Self::CodeBlock(code_block) => offset_range(&mut code_block.source_range, delta),
+            Self::Paragraph(text) => text.offset_source_ranges(delta),
+            Self::HorizontalRule(range) => offset_range(range, delta),
+        }
+    }
+
+    /// Whether the block's text is written right to left.
+    /// Blocks without text of their own are laid out left to right.
+    pub fn is_right_to_left(&self) -> bool {
+        match self {
+            Self::Paragraph(text) => text.is_right_to_left(),
+            Self::Heading(heading) => heading.contents.is_right_to_left(),
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -36,6 +94,55 @@ pub struct ParsedMarkdown {
     pub children: Vec<ParsedMarkdownElement>,
 }
 
+impl ParsedMarkdown {
+    /// Replaces the blocks at `range` with `blocks`, which were re-parsed after an edit,
+    /// moving the blocks after them by `delta`, the change in length of the source.
+    pub fn splice(
+        &mut self,
+        range: Range<usize>,
+        blocks: Vec<ParsedMarkdownElement>,
+        delta: isize,
+    ) {
+        if delta != 0 {
+            for block in &mut self.children[range.end..] {
+                block.offset_source_ranges(delta);
+            }
+        }
+        self.children.splice(range, blocks);
+    }
+
+    /// Every code block in the document, including those in lists and block quotes.
+    pub fn code_blocks_mut(&mut self) -> Vec<&mut ParsedMarkdownCodeBlock> {
+        fn collect<'a>(
+            block: &'a mut ParsedMarkdownElement,
+            code_blocks: &mut Vec<&'a mut ParsedMarkdownCodeBlock>,
+        ) {
+            match block {
+                ParsedMarkdownElement::CodeBlock(code_block) => code_blocks.push(code_block),
+                ParsedMarkdownElement::List(list) => {
+                    for item in &mut list.children {
+                        for block in &mut item.contents {
+                            collect(block, code_blocks);
+                        }
+                    }
+                }
+                ParsedMarkdownElement::BlockQuote(block_quote) => {
+                    for block in &mut block_quote.children {
+                        collect(block, code_blocks);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut code_blocks = Vec::new();
+        for block in &mut self.children {
+            collect(block, &mut code_blocks);
+        }
+        code_blocks
+    }
+}
+
 #[derive(Debug)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct ParsedMarkdownList {
@@ -43,6 +150,32 @@ pub struct ParsedMarkdownList {
     pub children: Vec<ParsedMarkdownListItem>,
 }
 
+impl ParsedMarkdownList {
+    /// How many of the list's tasks are checked, and how many tasks it has,
+    /// including the tasks of the lists nested in it.
+    pub fn task_progress(&self) -> (usize, usize) {
+        let mut completed = 0;
+        let mut total = 0;
+        for item in &self.children {
+            if let ParsedMarkdownListItemType::Task(checked) = item.item_type {
+                total += 1;
+                if checked {
+                    completed += 1;
+                }
+            }
+
+            for block in &item.contents {
+                if let ParsedMarkdownElement::List(list) = block.as_ref() {
+                    let (nested_completed, nested_total) = list.task_progress();
+                    completed += nested_completed;
+                    total += nested_total;
+                }
+            }
+        }
+        (completed, total)
+    }
+}
+
 #[derive(Debug)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct ParsedMarkdownListItem {
@@ -64,8 +197,21 @@ pub enum ParsedMarkdownListItemType {
 #[cfg_attr(test, derive(PartialEq))]
 pub struct ParsedMarkdownCodeBlock {
     pub source_range: Range<usize>,
+    /// The language the fence names, or that the code was detected to be written in,
+    /// as either a language name or a file extension, e.g. `py`.
     pub language: Option<String>,
+    /// The name of the language the code was highlighted in, once it has been loaded.
+    pub language_name: Option<SharedString>,
+    /// The `title` attribute of the fence, e.g. ```` ```rust title=main.rs ````.
+    pub title: Option<String>,
+    /// The `theme` attribute of the fence, naming the theme to highlight it with
+    /// instead of the preview's code block theme.
+    pub theme: Option<String>,
     pub contents: SharedString,
+    /// The syntax highlights of the code, once its language has been loaded.
+    pub highlights: Vec<(Range<usize>, HighlightId)>,
+    /// The code rendered as a diagram, for languages such as Graphviz's DOT.
+    pub diagram: Option<LoadedMarkdownImage>,
 }
 
 #[derive(Debug)]
@@ -76,7 +222,7 @@ pub struct ParsedMarkdownHeading {
     pub contents: ParsedMarkdownText,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, PartialOrd)]
 pub enum HeadingLevel {
     H1,
     H2,
@@ -92,6 +238,8 @@ pub struct ParsedMarkdownTable {
     pub header: ParsedMarkdownTableRow,
     pub body: Vec<ParsedMarkdownTableRow>,
     pub column_alignments: Vec<ParsedMarkdownTableAlignment>,
+    /// The caption written after the table, as in `Table: Caption`.
+    pub caption: Option<ParsedMarkdownText>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -141,6 +289,112 @@ pub struct ParsedMarkdownText {
     pub region_ranges: Vec<Range<usize>>,
     /// The regions of the Markdown document.
     pub regions: Vec<ParsedRegion>,
+    /// The images in the text that the preview can display.
+    pub images: Vec<ParsedMarkdownImage>,
+}
+
+impl ParsedMarkdownText {
+    /// Whether the text is written right to left, such as in Arabic or Hebrew.
+    /// As in Unicode's bidirectional algorithm, this is decided by the first letter
+    /// of the text, so a paragraph that starts in English is laid out left to right.
+    pub fn is_right_to_left(&self) -> bool {
+        self.contents
+            .chars()
+            .find(|c| c.is_alphabetic())
+            .map_or(false, |c| {
+                matches!(
+                    c,
+                    '\u{0590}'..='\u{08FF}'
+                        | '\u{FB1D}'..='\u{FDFF}'
+                        | '\u{FE70}'..='\u{FEFF}'
+                        | '\u{10800}'..='\u{10FFF}'
+                        | '\u{1E800}'..='\u{1EFFF}'
+                )
+            })
+    }
+
+    fn offset_source_ranges(&mut self, delta: isize) {
+        offset_range(&mut self.source_range, delta);
+        for image in &mut self.images {
+            offset_range(&mut image.source_range, delta);
+        }
+    }
+
+    /// Whether the text consists of nothing but images, such as a row of badges,
+    /// in which case only the images need to be shown.
+    pub fn is_only_images(&self) -> bool {
+        if self.images.is_empty() {
+            return false;
+        }
+
+        let mut text_start = 0;
+        for image in &self.images {
+            if !self.contents[text_start..image.alt_text_range.start]
+                .trim()
+                .is_empty()
+            {
+                return false;
+            }
+            text_start = image.alt_text_range.end;
+        }
+        self.contents[text_start..].trim().is_empty()
+    }
+}
+
+fn offset_range(range: &mut Range<usize>, delta: isize) {
+    range.start = range.start.saturating_add_signed(delta);
+    range.end = range.end.saturating_add_signed(delta);
+}
+
+/// An image in a Markdown document.
+#[derive(Debug, Clone)]
+pub struct ParsedMarkdownImage {
+    /// Where the image is located in the source Markdown document.
+    pub source_range: Range<usize>,
+    /// Where the image's alt text is located in the text's contents.
+    pub alt_text_range: Range<usize>,
+    pub source: MarkdownImageSource,
+    /// The decoded image, once it has been loaded.
+    pub loaded: Option<LoadedMarkdownImage>,
+}
+
+/// Where the data for a [`ParsedMarkdownImage`] comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MarkdownImageSource {
+    /// An image file on disk, which may be an SVG.
+    Path(PathBuf),
+    /// An image embedded in the document as a `data:` URI.
+    DataUri(String),
+}
+
+impl MarkdownImageSource {
+    /// Returns the source of an image the preview can display, which is either
+    /// a `data:` URI or an image file that exists on disk.
+    pub fn identify(file_location_directory: Option<&Path>, url: &str) -> Option<Self> {
+        if url.starts_with("data:") {
+            return Some(Self::DataUri(url.to_string()));
+        }
+
+        let path = PathBuf::from(url);
+        let path = if path.is_absolute() {
+            path
+        } else {
+            file_location_directory?.join(path)
+        };
+        path.is_file().then(|| Self::Path(path))
+    }
+}
+
+/// An image that has been decoded, ready to be painted.
+#[derive(Debug, Clone)]
+pub struct LoadedMarkdownImage {
+    pub data: Arc<ImageData>,
+    /// The size to display the image at, independent of the display's scale.
+    pub size: Size<Pixels>,
+    /// The scale factor the image was loaded at, which SVGs are rasterized at.
+    pub scale_factor: f32,
+    /// When the image's file was last modified, as of when it was decoded.
+    pub modified: Option<SystemTime>,
 }
 
 /// A run of highlighted Markdown text.
@@ -170,6 +424,13 @@ impl MarkdownHighlight {
                     });
                 }
 
+                if style.strikethrough {
+                    highlight.strikethrough = Some(StrikethroughStyle {
+                        thickness: px(1.),
+                        ..Default::default()
+                    });
+                }
+
                 if style.weight != FontWeight::default() {
                     highlight.font_weight = Some(style.weight);
                 }
@@ -189,6 +450,13 @@ pub struct MarkdownHighlightStyle {
     pub italic: bool,
     /// Whether the text should be underlined.
     pub underline: bool,
+    /// Whether the text should be struck through.
+    pub strikethrough: bool,
+    /// Whether the text should be highlighted.
+    pub highlight: bool,
+    /// Whether the text is a superscript or subscript that has no Unicode form,
+    /// and so is shown in its own characters, set apart from the text around it.
+    pub script_fallback: bool,
     /// The weight of the text.
     pub weight: FontWeight,
 }
@@ -201,6 +469,8 @@ pub struct ParsedRegion {
     pub code: bool,
     /// The link contained in this region, if it has one.
     pub link: Option<Link>,
+    /// The expansion of the abbreviation in this region, if it is one.
+    pub abbreviation: Option<SharedString>,
 }
 
 /// A Markdown link.
@@ -217,6 +487,47 @@ pub enum Link {
         /// The path to the item.
         path: PathBuf,
     },
+    /// A link to a heading in the same document.
+    Heading {
+        /// The heading's anchor, without the leading `#`.
+        anchor: String,
+        /// The Markdown source of the heading's section, previewed on hover.
+        section: SharedString,
+    },
+    /// A reference to a footnote.
+    Footnote {
+        label: String,
+        /// The Markdown source of the footnote's text, previewed on hover.
+        text: SharedString,
+    },
+    /// A link whose destination couldn't be found.
+    Broken {
+        /// The destination, as written in the Markdown document.
+        destination: String,
+        /// Why the destination couldn't be found.
+        reason: BrokenLinkReason,
+    },
+}
+
+/// The reason a [`Link::Broken`] couldn't be resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrokenLinkReason {
+    /// The linked file doesn't exist on disk.
+    FileNotFound,
+    /// No heading in the document matches the linked anchor.
+    HeadingNotFound,
+    /// No footnote in the document has the referenced label.
+    FootnoteNotFound,
+}
+
+impl BrokenLinkReason {
+    pub fn message(&self) -> &'static str {
+        match self {
+            BrokenLinkReason::FileNotFound => "File not found",
+            BrokenLinkReason::HeadingNotFound => "No heading matches this anchor",
+            BrokenLinkReason::FootnoteNotFound => "No footnote has this label",
+        }
+    }
 }
 
 impl Link {
@@ -225,18 +536,50 @@ impl Link {
             return Some(Link::Web { url: text });
         }
 
-        let path = PathBuf::from(&text);
-        if path.is_absolute() && path.exists() {
-            return Some(Link::Path { path });
+        // Other URL schemes, such as `mailto:`, aren't links we can follow or check.
+        if let Some((scheme, _)) = text.split_once(':') {
+            if scheme.len() > 1 && scheme.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return None;
+            }
         }
 
-        if let Some(file_location_directory) = file_location_directory {
-            let path = file_location_directory.join(text);
-            if path.exists() {
-                return Some(Link::Path { path });
-            }
+        // Anchors and queries within a linked file aren't checked, only the file itself.
+        let path_text = text.split(['#', '?']).next().unwrap_or_default();
+        if path_text.is_empty() {
+            return None;
         }
 
-        None
+        // Names with spaces and other reserved characters are percent-encoded,
+        // as in `[notes](my%20notes.md)`.
+        let path_text = urlencoding::decode(path_text).unwrap_or(path_text.into());
+        let path = PathBuf::from(path_text.as_ref());
+        let path = if path.is_absolute() {
+            path
+        } else if let Some(file_location_directory) = file_location_directory {
+            file_location_directory.join(path)
+        } else {
+            return None;
+        };
+
+        if path.exists() {
+            Some(Link::Path { path })
+        } else {
+            Some(Link::Broken {
+                destination: text,
+                reason: BrokenLinkReason::FileNotFound,
+            })
+        }
+    }
+}
+
+impl Display for Link {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Link::Web { url } => write!(f, "{}", url),
+            Link::Path { path } => write!(f, "{}", path.display()),
+            Link::Heading { anchor, .. } => write!(f, "#{}", anchor),
+            Link::Footnote { label, .. } => write!(f, "[^{}]", label),
+            Link::Broken { destination, .. } => write!(f, "{}", destination),
+        }
     }
 }
@@ -0,0 +1,346 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write as _,
+    path::Path,
+    process::{Command, Stdio},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Context as _, Result};
+use gpui::{px, size, ImageData, SharedString};
+use image::{Bgra, ImageBuffer};
+
+use crate::markdown_elements::{
+    LoadedMarkdownImage, MarkdownImageSource, ParsedMarkdown, ParsedMarkdownCodeBlock,
+    ParsedMarkdownElement, ParsedMarkdownImage,
+};
+
+/// Loads the images and diagrams of a parsed document.
+pub struct ImageLoader {
+    /// SVGs are rasterized at this scale factor, so they stay sharp.
+    pub scale_factor: f32,
+    /// Whether to load images, rather than leaving them to be shown as their alt text.
+    pub images: bool,
+    /// Whether to render Graphviz code blocks as diagrams.
+    pub graphviz_diagrams: bool,
+    /// Diagrams that have already been rendered, keyed by their code,
+    /// so that unchanged diagrams aren't rendered again at the same scale factor.
+    pub previous_diagrams: HashMap<SharedString, LoadedMarkdownImage>,
+    /// Images that have already been decoded, keyed by their source, so that
+    /// images aren't decoded again unless their file or the scale factor has changed.
+    pub previous_images: HashMap<MarkdownImageSource, LoadedMarkdownImage>,
+}
+
+impl ImageLoader {
+    /// Decodes the images in the document that the preview can display,
+    /// and renders its diagrams.
+    pub fn load(&self, markdown: &mut ParsedMarkdown) {
+        for block in &mut markdown.children {
+            self.load_block(block);
+        }
+    }
+
+    fn load_block(&self, block: &mut ParsedMarkdownElement) {
+        match block {
+            ParsedMarkdownElement::Paragraph(text) => {
+                for image in &mut text.images {
+                    self.load_image(image);
+                }
+            }
+            ParsedMarkdownElement::Table(table) => {
+                for row in std::iter::once(&mut table.header).chain(&mut table.body) {
+                    for cell in &mut row.children {
+                        for image in &mut cell.images {
+                            self.load_image(image);
+                        }
+                    }
+                }
+            }
+            ParsedMarkdownElement::CodeBlock(code_block) => {
+                if self.graphviz_diagrams && is_graphviz(code_block) {
+                    self.render_diagram(code_block);
+                }
+            }
+            ParsedMarkdownElement::List(list) => {
+                for item in &mut list.children {
+                    for block in &mut item.contents {
+                        self.load_block(block);
+                    }
+                }
+            }
+            ParsedMarkdownElement::BlockQuote(block_quote) => {
+                for block in &mut block_quote.children {
+                    self.load_block(block);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn load_image(&self, image: &mut ParsedMarkdownImage) {
+        if !self.images {
+            return;
+        }
+
+        if let Some(loaded) = self.previous_images.get(&image.source) {
+            if loaded.scale_factor == self.scale_factor
+                && loaded.modified == modified_time(&image.source)
+            {
+                image.loaded = Some(loaded.clone());
+                return;
+            }
+        }
+
+        match decode_image(&image.source, self.scale_factor) {
+            Ok(loaded) => image.loaded = Some(loaded),
+            Err(error) => log::warn!("failed to load markdown preview image: {error:?}"),
+        }
+    }
+
+    fn render_diagram(&self, code_block: &mut ParsedMarkdownCodeBlock) {
+        if let Some(diagram) = self.previous_diagrams.get(&code_block.contents) {
+            if diagram.scale_factor == self.scale_factor {
+                code_block.diagram = Some(diagram.clone());
+                return;
+            }
+        }
+
+        match render_graphviz(&code_block.contents, self.scale_factor) {
+            Ok(diagram) => code_block.diagram = Some(diagram),
+            Err(error) => log::warn!("failed to render markdown preview diagram: {error:?}"),
+        }
+    }
+}
+
+/// The extensions of the image files that the preview can display.
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "bmp", "webp", "svg"];
+
+/// Whether the file at the given path is an image that the preview can display.
+pub fn is_image_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| {
+            IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+        })
+}
+
+/// The diagrams that have been rendered in the given blocks, keyed by their code.
+pub fn rendered_diagrams(
+    blocks: &[ParsedMarkdownElement],
+) -> HashMap<SharedString, LoadedMarkdownImage> {
+    fn collect(
+        block: &ParsedMarkdownElement,
+        diagrams: &mut HashMap<SharedString, LoadedMarkdownImage>,
+    ) {
+        match block {
+            ParsedMarkdownElement::CodeBlock(code_block) => {
+                if let Some(diagram) = &code_block.diagram {
+                    diagrams.insert(code_block.contents.clone(), diagram.clone());
+                }
+            }
+            ParsedMarkdownElement::List(list) => {
+                for item in &list.children {
+                    for block in &item.contents {
+                        collect(block, diagrams);
+                    }
+                }
+            }
+            ParsedMarkdownElement::BlockQuote(block_quote) => {
+                for block in &block_quote.children {
+                    collect(block, diagrams);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut diagrams = HashMap::default();
+    for block in blocks {
+        collect(block, &mut diagrams);
+    }
+    diagrams
+}
+
+/// The images that have been decoded in the given blocks, keyed by their source.
+pub fn loaded_images(
+    blocks: &[ParsedMarkdownElement],
+) -> HashMap<MarkdownImageSource, LoadedMarkdownImage> {
+    fn collect_from(
+        images: &[ParsedMarkdownImage],
+        loaded_images: &mut HashMap<MarkdownImageSource, LoadedMarkdownImage>,
+    ) {
+        for image in images {
+            if let Some(loaded) = &image.loaded {
+                loaded_images.insert(image.source.clone(), loaded.clone());
+            }
+        }
+    }
+
+    fn collect(
+        block: &ParsedMarkdownElement,
+        loaded_images: &mut HashMap<MarkdownImageSource, LoadedMarkdownImage>,
+    ) {
+        match block {
+            ParsedMarkdownElement::Paragraph(text) => collect_from(&text.images, loaded_images),
+            ParsedMarkdownElement::Table(table) => {
+                for row in std::iter::once(&table.header).chain(&table.body) {
+                    for cell in &row.children {
+                        collect_from(&cell.images, loaded_images);
+                    }
+                }
+            }
+            ParsedMarkdownElement::List(list) => {
+                for item in &list.children {
+                    for block in &item.contents {
+                        collect(block, loaded_images);
+                    }
+                }
+            }
+            ParsedMarkdownElement::BlockQuote(block_quote) => {
+                for block in &block_quote.children {
+                    collect(block, loaded_images);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut loaded_images = HashMap::default();
+    for block in blocks {
+        collect(block, &mut loaded_images);
+    }
+    loaded_images
+}
+
+fn is_graphviz(code_block: &ParsedMarkdownCodeBlock) -> bool {
+    code_block.language.as_deref().map_or(false, |language| {
+        language.eq_ignore_ascii_case("dot") || language.eq_ignore_ascii_case("graphviz")
+    })
+}
+
+/// Lays out a graph with Graphviz's `dot` command, and rasterizes the SVG it produces.
+fn render_graphviz(source: &str, scale_factor: f32) -> Result<LoadedMarkdownImage> {
+    let mut dot = Command::new("dot")
+        .arg("-Tsvg")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to run Graphviz's `dot` command")?;
+    dot.stdin
+        .take()
+        .context("failed to open `dot`'s stdin")?
+        .write_all(source.as_bytes())?;
+
+    let output = dot.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "`dot` failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    rasterize_svg(&output.stdout, scale_factor)
+}
+
+/// When the file of an image was last modified, or `None` for images embedded in the document.
+fn modified_time(source: &MarkdownImageSource) -> Option<SystemTime> {
+    match source {
+        MarkdownImageSource::Path(path) => fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok(),
+        MarkdownImageSource::DataUri(_) => None,
+    }
+}
+
+fn decode_image(source: &MarkdownImageSource, scale_factor: f32) -> Result<LoadedMarkdownImage> {
+    let modified = modified_time(source);
+    let (bytes, is_svg) = match source {
+        MarkdownImageSource::Path(path) => {
+            let bytes = fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+            let is_svg = path
+                .extension()
+                .map_or(false, |extension| extension == "svg");
+            (bytes, is_svg)
+        }
+        MarkdownImageSource::DataUri(uri) => {
+            let (header, data) = uri
+                .strip_prefix("data:")
+                .and_then(|uri| uri.split_once(','))
+                .context("invalid data URI")?;
+            let bytes = if header.ends_with(";base64") {
+                base64::decode(data)?
+            } else {
+                // Unlike base64 data, plain data may contain percent-encoded bytes,
+                // as in `data:image/svg+xml,%3Csvg ...%3E`.
+                urlencoding::decode_binary(data.as_bytes()).into_owned()
+            };
+            (bytes, header.starts_with("image/svg+xml"))
+        }
+    };
+
+    if is_svg {
+        return Ok(LoadedMarkdownImage {
+            modified,
+            ..rasterize_svg(&bytes, scale_factor)?
+        });
+    }
+
+    // Bitmaps are shown at one image pixel per logical pixel, as browsers do.
+    let image = image::load_from_memory(&bytes)?.into_bgra8();
+    let image_size = size(px(image.width() as f32), px(image.height() as f32));
+    Ok(LoadedMarkdownImage {
+        data: Arc::new(ImageData::new(image)),
+        size: image_size,
+        scale_factor,
+        modified,
+    })
+}
+
+fn rasterize_svg(bytes: &[u8], scale_factor: f32) -> Result<LoadedMarkdownImage> {
+    let tree = usvg::Tree::from_data(bytes, &usvg::Options::default())?;
+    let svg_size = tree.svg_node().size;
+    let width = (svg_size.width() as f32 * scale_factor).ceil() as u32;
+    let height = (svg_size.height() as f32 * scale_factor).ceil() as u32;
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).context("SVG has no size")?;
+    resvg::render(&tree, usvg::FitTo::Width(width), pixmap.as_mut())
+        .context("failed to render SVG")?;
+
+    // The pixmap is premultiplied RGBA, but images are painted from straight BGRA.
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.blue(), color.green(), color.red(), color.alpha()]
+        })
+        .collect();
+    let image = ImageBuffer::<Bgra<u8>, Vec<u8>>::from_raw(width, height, pixels)
+        .context("SVG pixels don't match its size")?;
+
+    Ok(LoadedMarkdownImage {
+        data: Arc::new(ImageData::new(image)),
+        size: size(px(svg_size.width() as f32), px(svg_size.height() as f32)),
+        scale_factor,
+        modified: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_percent_encoded_data_uri() {
+        let source = MarkdownImageSource::DataUri(
+            "data:image/svg+xml,%3Csvg%20xmlns%3D%22http%3A%2F%2Fwww.w3.org%2F2000%2Fsvg%22%20width%3D%2220%22%20height%3D%2210%22%2F%3E"
+                .to_string(),
+        );
+        let image = decode_image(&source, 2.).unwrap();
+        assert_eq!(image.size, size(px(20.), px(10.)));
+    }
+}
@@ -1,21 +1,63 @@
 use crate::markdown_elements::*;
-use gpui::FontWeight;
-use pulldown_cmark::{Alignment, Event, Options, Parser, Tag};
-use std::{ops::Range, path::PathBuf};
-
+use gpui::{FontWeight, SharedString};
+use pulldown_cmark::{Alignment, CowStr, Event, LinkType, Options, Parser, Tag};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// The destination prefix given to `[[wiki links]]`, so they can be told
+/// apart from ordinary links once they've been rewritten into them.
+const WIKI_LINK_PREFIX: &str = "wiki:";
+
+/// Parses the given Markdown. `[[wiki links]]` are resolved against
+/// `markdown_files`, the Markdown files in the document's worktree.
+/// With `hard_line_breaks`, single newlines within a paragraph start a new line,
+/// as on GitHub, instead of being joined into one line as CommonMark specifies.
 pub fn parse_markdown(
     markdown_input: &str,
     file_location_directory: Option<PathBuf>,
+    markdown_files: &[PathBuf],
+    smart_typography: bool,
+    hard_line_breaks: bool,
 ) -> ParsedMarkdown {
-    let options = Options::all();
+    let mut options = Options::all();
+    if !smart_typography {
+        options.remove(Options::ENABLE_SMART_PUNCTUATION);
+    }
     let parser = Parser::new_ext(markdown_input, options);
-    let parser = MarkdownParser::new(parser.into_offset_iter().collect(), file_location_directory);
+    let parser = MarkdownParser::new(
+        markdown_input,
+        parser.into_offset_iter().collect(),
+        file_location_directory,
+        markdown_files,
+        hard_line_breaks,
+    );
     let renderer = parser.parse_document();
     ParsedMarkdown {
         children: renderer.parsed,
     }
 }
 
+/// Whether the Markdown may render differently depending on the rest of its document,
+/// through reference, footnote or abbreviation definitions, or links to headings.
+/// Such documents have to be parsed as a whole after every edit.
+pub fn has_document_references(markdown_input: &str) -> bool {
+    markdown_input.contains("]:") || markdown_input.contains("](#")
+}
+
+/// Whether the blocks in the Markdown parse the same on their own as they do
+/// in their document, so that they can be re-parsed without the rest of it
+/// after an edit. Fenced code and HTML can run on past the blocks around them.
+pub fn can_parse_in_isolation(markdown_input: &str) -> bool {
+    !has_document_references(markdown_input)
+        && !markdown_input.contains("```")
+        && !markdown_input.contains("~~~")
+        && !markdown_input.contains('<')
+}
+
 struct MarkdownParser<'a> {
     tokens: Vec<(Event<'a>, Range<usize>)>,
     /// The current index in the tokens array
@@ -23,21 +65,303 @@ struct MarkdownParser<'a> {
     /// The blocks that we have successfully parsed so far
     parsed: Vec<ParsedMarkdownElement>,
     file_location_directory: Option<PathBuf>,
+    /// The Markdown files that `[[wiki links]]` can point to
+    markdown_files: &'a [PathBuf],
+    /// The source of each heading's section, by the heading's anchors,
+    /// used to check and preview `#anchor` links
+    heading_sections: HashMap<String, SharedString>,
+    /// The source of each footnote's text, by the footnote's label
+    footnotes: HashMap<String, SharedString>,
+    /// The expansion of each abbreviation defined in the document
+    abbreviations: HashMap<String, SharedString>,
+    /// Whether soft breaks within a paragraph are rendered as line breaks
+    hard_line_breaks: bool,
+    /// Whether a table cell is being parsed, where `\|` escapes a pipe even in code
+    in_table_cell: bool,
 }
 
 impl<'a> MarkdownParser<'a> {
     fn new(
+        markdown_input: &str,
         tokens: Vec<(Event<'a>, Range<usize>)>,
         file_location_directory: Option<PathBuf>,
+        markdown_files: &'a [PathBuf],
+        hard_line_breaks: bool,
     ) -> Self {
+        let mut tokens = tokens;
+        let abbreviations = Self::take_abbreviations(markdown_input, &mut tokens);
+        let tokens = Self::replace_wiki_links(tokens);
+        let heading_sections = Self::heading_sections(markdown_input, &tokens);
+        let footnotes = Self::footnotes(markdown_input, &tokens);
         Self {
             tokens,
             file_location_directory,
+            markdown_files,
+            heading_sections,
+            footnotes,
+            abbreviations,
+            hard_line_breaks,
+            in_table_cell: false,
             cursor: 0,
             parsed: vec![],
         }
     }
 
+    /// Finds the section of every heading, which runs until the next heading
+    /// of the same or a higher level, and maps each of the heading's anchors to it.
+    fn heading_sections(
+        markdown_input: &str,
+        tokens: &[(Event<'a>, Range<usize>)],
+    ) -> HashMap<String, SharedString> {
+        // The anchors, level and start of every heading, in order.
+        let mut headings: Vec<(Vec<String>, pulldown_cmark::HeadingLevel, usize)> = vec![];
+        let mut heading_text: Option<String> = None;
+
+        for (event, range) in tokens {
+            match event {
+                Event::Start(Tag::Heading(level, id, _)) => {
+                    let anchors = id.iter().map(|id| id.to_string()).collect();
+                    headings.push((anchors, *level, range.start));
+                    heading_text = Some(String::new());
+                }
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some(heading_text) = heading_text.as_mut() {
+                        heading_text.push_str(text);
+                    }
+                }
+                Event::End(Tag::Heading(_, _, _)) => {
+                    if let Some(heading_text) = heading_text.take() {
+                        if let Some((anchors, _, _)) = headings.last_mut() {
+                            anchors.push(heading_anchor(&heading_text));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut sections = HashMap::default();
+        for (ix, (anchors, level, start)) in headings.iter().enumerate() {
+            let end = headings[ix + 1..]
+                .iter()
+                .find(|(_, next_level, _)| next_level <= level)
+                .map_or(markdown_input.len(), |(_, _, next_start)| *next_start);
+            let section = SharedString::from(markdown_input[*start..end].trim().to_string());
+            for anchor in anchors {
+                sections.insert(anchor.clone(), section.clone());
+            }
+        }
+        sections
+    }
+
+    /// Takes the paragraphs that define abbreviations, such as
+    /// `*[HTML]: HyperText Markup Language`, out of the document,
+    /// and returns the abbreviations they define.
+    fn take_abbreviations(
+        markdown_input: &str,
+        tokens: &mut Vec<(Event<'a>, Range<usize>)>,
+    ) -> HashMap<String, SharedString> {
+        let mut abbreviations = HashMap::default();
+        let mut ix = 0;
+        while ix < tokens.len() {
+            let (Event::Start(Tag::Paragraph), range) = &tokens[ix] else {
+                ix += 1;
+                continue;
+            };
+            let definitions = markdown_input[range.clone()]
+                .lines()
+                .map(abbreviation_definition)
+                .collect::<Option<Vec<_>>>()
+                .filter(|definitions| !definitions.is_empty());
+            let Some(definitions) = definitions else {
+                ix += 1;
+                continue;
+            };
+
+            for (abbreviation, expansion) in definitions {
+                abbreviations.insert(abbreviation.to_string(), expansion.to_string().into());
+            }
+            let end = tokens[ix..]
+                .iter()
+                .position(|(event, _)| matches!(event, Event::End(Tag::Paragraph)))
+                .map_or(tokens.len(), |end| ix + end + 1);
+            tokens.drain(ix..end);
+        }
+        abbreviations
+    }
+
+    /// Finds the abbreviations that occur in the text as whole words,
+    /// preferring the longest where they overlap.
+    fn abbreviations_in(&self, text: &str) -> Vec<(Range<usize>, SharedString)> {
+        let mut occurrences = Vec::new();
+        for (abbreviation, expansion) in &self.abbreviations {
+            for (start, _) in text.match_indices(abbreviation.as_str()) {
+                let end = start + abbreviation.len();
+                let is_word_start = text[..start]
+                    .chars()
+                    .next_back()
+                    .map_or(true, |c| !c.is_alphanumeric());
+                let is_word_end = text[end..]
+                    .chars()
+                    .next()
+                    .map_or(true, |c| !c.is_alphanumeric());
+                if is_word_start && is_word_end {
+                    occurrences.push((start..end, expansion.clone()));
+                }
+            }
+        }
+        occurrences.sort_by_key(|(range, _)| (range.start, Reverse(range.end)));
+        occurrences.dedup_by(|(range, _), (previous_range, _)| range.start < previous_range.end);
+        occurrences
+    }
+
+    /// Finds the text of every footnote definition, without its `[^label]:` prefix.
+    fn footnotes(
+        markdown_input: &str,
+        tokens: &[(Event<'a>, Range<usize>)],
+    ) -> HashMap<String, SharedString> {
+        let mut footnotes = HashMap::default();
+        for (ix, (event, range)) in tokens.iter().enumerate() {
+            let Event::Start(Tag::FootnoteDefinition(label)) = event else {
+                continue;
+            };
+            let Some((_, text_range)) = tokens.get(ix + 1) else {
+                continue;
+            };
+            let start = text_range.start.min(range.end);
+
+            // The lines after the first are indented under the label, so that
+            // they're part of the footnote; unindent them so they parse the same.
+            let text = markdown_input[start..range.end]
+                .lines()
+                .map(|line| {
+                    let indent = line.len() - line.trim_start_matches(' ').len();
+                    &line[indent.min(4)..]
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            footnotes.insert(label.to_string(), text.trim().to_string().into());
+        }
+        footnotes
+    }
+
+    /// Rewrites `[[target]]` and `[[target|label]]` into ordinary links,
+    /// so they're rendered like any other link. pulldown-cmark doesn't know
+    /// about wiki links, and emits each of their brackets as a separate text event.
+    fn replace_wiki_links(
+        tokens: Vec<(Event<'a>, Range<usize>)>,
+    ) -> Vec<(Event<'a>, Range<usize>)> {
+        let mut replaced = Vec::with_capacity(tokens.len());
+        let mut ix = 0;
+        while ix < tokens.len() {
+            if let Some((link, next_ix)) = Self::wiki_link_at(&tokens, ix) {
+                replaced.extend(link);
+                ix = next_ix;
+            } else {
+                replaced.push(tokens[ix].clone());
+                ix += 1;
+            }
+        }
+        replaced
+    }
+
+    /// Returns the link events for the wiki link starting at `start`, along
+    /// with the index of the first token after it.
+    fn wiki_link_at(
+        tokens: &[(Event<'a>, Range<usize>)],
+        start: usize,
+    ) -> Option<([(Event<'a>, Range<usize>); 3], usize)> {
+        let is_bracket = |ix: usize, bracket: &str| {
+            matches!(
+                tokens.get(ix),
+                Some((Event::Text(text), range)) if text.as_ref() == bracket && range.len() == 1
+            )
+        };
+        if !is_bracket(start, "[") || !is_bracket(start + 1, "[") {
+            return None;
+        }
+
+        let mut contents = String::new();
+        let mut end = start + 2;
+        loop {
+            match tokens.get(end)? {
+                (Event::Text(_), _) if is_bracket(end, "]") && is_bracket(end + 1, "]") => break,
+                (Event::Text(text), _) => contents.push_str(text),
+                _ => return None,
+            }
+            end += 1;
+        }
+
+        let (target, label) = contents.split_once('|').unwrap_or((&contents, &contents));
+        let (target, label) = (target.trim(), label.trim());
+        if target.is_empty() || label.is_empty() {
+            return None;
+        }
+
+        let range = tokens[start].1.start..tokens[end + 1].1.end;
+        let label_range = tokens[start + 1].1.end..tokens[end].1.start;
+        let tag = Tag::Link(
+            LinkType::Inline,
+            format!("{WIKI_LINK_PREFIX}{target}").into(),
+            CowStr::Borrowed(""),
+        );
+        let link = [
+            (Event::Start(tag.clone()), range.clone()),
+            (Event::Text(label.to_string().into()), label_range),
+            (Event::End(tag), range),
+        ];
+        Some((link, end + 2))
+    }
+
+    /// Finds the Markdown file a wiki link points to, either by its path
+    /// (`[[notes/page]]`) or by its title (`[[Page Name]]` for `page-name.md`).
+    fn resolve_wiki_link(&self, target: &str) -> Link {
+        let target = target.split('#').next().unwrap_or_default();
+        let target = target.strip_suffix(".md").unwrap_or(target);
+        let target_path = Path::new(target);
+        let target_slug = heading_anchor(target);
+
+        let path = self.markdown_files.iter().find(|path| {
+            let path = path.with_extension("");
+            if target.contains('/') {
+                path.ends_with(target_path)
+            } else {
+                path.file_name().map_or(false, |name| {
+                    heading_anchor(&name.to_string_lossy()) == target_slug
+                })
+            }
+        });
+
+        match path {
+            Some(path) => Link::Path { path: path.clone() },
+            None => Link::Broken {
+                destination: target.to_string(),
+                reason: BrokenLinkReason::FileNotFound,
+            },
+        }
+    }
+
+    fn identify_link(&self, url: &str) -> Option<Link> {
+        if let Some(target) = url.strip_prefix(WIKI_LINK_PREFIX) {
+            return Some(self.resolve_wiki_link(target));
+        }
+
+        if let Some(anchor) = url.strip_prefix('#') {
+            if let Some(section) = self.heading_sections.get(anchor) {
+                return Some(Link::Heading {
+                    anchor: anchor.to_string(),
+                    section: section.clone(),
+                });
+            }
+            return Some(Link::Broken {
+                destination: url.to_string(),
+                reason: BrokenLinkReason::HeadingNotFound,
+            });
+        }
+
+        Link::identify(self.file_location_directory.clone(), url.to_string())
+    }
+
     fn eof(&self) -> bool {
         if self.tokens.is_empty() {
             return true;
@@ -122,20 +446,14 @@ impl<'a> MarkdownParser<'a> {
                     Some(ParsedMarkdownElement::BlockQuote(block_quote))
                 }
                 Tag::CodeBlock(kind) => {
-                    let language = match kind {
-                        pulldown_cmark::CodeBlockKind::Indented => None,
-                        pulldown_cmark::CodeBlockKind::Fenced(language) => {
-                            if language.is_empty() {
-                                None
-                            } else {
-                                Some(language.to_string())
-                            }
-                        }
+                    let fence = match kind {
+                        pulldown_cmark::CodeBlockKind::Indented => FenceInfo::default(),
+                        pulldown_cmark::CodeBlockKind::Fenced(info) => Self::parse_fence_info(info),
                     };
 
                     self.cursor += 1;
 
-                    let code_block = self.parse_code_block(language);
+                    let code_block = self.parse_code_block(fence);
                     Some(ParsedMarkdownElement::CodeBlock(code_block))
                 }
                 _ => {
@@ -162,7 +480,13 @@ impl<'a> MarkdownParser<'a> {
         let mut text = String::new();
         let mut bold_depth = 0;
         let mut italic_depth = 0;
+        let mut strikethrough_depth = 0;
+        let mut subscript_depth = 0;
+        let mut strikethrough_kinds: Vec<bool> = vec![];
         let mut link: Option<Link> = None;
+        // The source of the image being parsed, and where its alt text starts.
+        let mut image: Option<(MarkdownImageSource, usize)> = None;
+        let mut images: Vec<ParsedMarkdownImage> = vec![];
         let mut region_ranges: Vec<Range<usize>> = vec![];
         let mut regions: Vec<ParsedRegion> = vec![];
         let mut highlights: Vec<(Range<usize>, MarkdownHighlight)> = vec![];
@@ -172,7 +496,7 @@ impl<'a> MarkdownParser<'a> {
                 break;
             }
 
-            let (current, _source_range) = self.current().unwrap();
+            let (current, current_range) = self.current().unwrap();
             let prev_len = text.len();
             match current {
                 Event::SoftBreak => {
@@ -180,56 +504,138 @@ impl<'a> MarkdownParser<'a> {
                         break;
                     }
 
-                    // `Some text\nSome more text` should be treated as a single line.
-                    text.push(' ');
+                    // `Some text\nSome more text` should be treated as a single line,
+                    // unless single newlines are meant to be line breaks.
+                    if self.hard_line_breaks {
+                        text.push('\n');
+                    } else {
+                        text.push(' ');
+                    }
                 }
 
                 Event::HardBreak => {
-                    break;
+                    text.push('\n');
+                }
+
+                Event::Html(html) => {
+                    // Comments are hidden, and line breaks written as `<br>` are kept.
+                    // Any other inline HTML is shown as it was written.
+                    if is_line_break_tag(html) {
+                        text.push('\n');
+                    } else if !html.starts_with("<!--") {
+                        text.push_str(html);
+                    }
                 }
 
                 Event::Text(t) => {
-                    text.push_str(t.as_ref());
+                    for (segment, extension) in parse_inline_extensions(t.as_ref()) {
+                        let segment_start = text.len();
+                        let script = match extension {
+                            InlineExtension::Superscript => Some(superscript(segment)),
+                            InlineExtension::Subscript => Some(subscript(segment)),
+                            _ if subscript_depth > 0 => Some(subscript(segment)),
+                            _ => None,
+                        };
+                        // Runs with characters that have no superscript or subscript form,
+                        // such as `^TM^` or `~CO~`, are kept as they were written.
+                        let script_fallback = matches!(script, Some(None));
+                        text.push_str(script.flatten().as_deref().unwrap_or(segment));
+
+                        let mut style = MarkdownHighlightStyle::default();
+
+                        if bold_depth > 0 {
+                            style.weight = FontWeight::BOLD;
+                        }
 
-                    let mut style = MarkdownHighlightStyle::default();
+                        if italic_depth > 0 {
+                            style.italic = true;
+                        }
 
-                    if bold_depth > 0 {
-                        style.weight = FontWeight::BOLD;
-                    }
+                        if strikethrough_depth > 0 {
+                            style.strikethrough = true;
+                        }
 
-                    if italic_depth > 0 {
-                        style.italic = true;
-                    }
+                        if extension == InlineExtension::Highlight {
+                            style.highlight = true;
+                        }
 
-                    if let Some(link) = link.clone() {
-                        region_ranges.push(prev_len..text.len());
-                        regions.push(ParsedRegion {
-                            code: false,
-                            link: Some(link),
-                        });
-                        style.underline = true;
-                    }
+                        if script_fallback {
+                            style.script_fallback = true;
+                        }
 
-                    if style != MarkdownHighlightStyle::default() {
-                        let mut new_highlight = true;
-                        if let Some((last_range, MarkdownHighlight::Style(last_style))) =
-                            highlights.last_mut()
-                        {
-                            if last_range.end == prev_len && last_style == &style {
-                                last_range.end = text.len();
-                                new_highlight = false;
+                        if let Some(link) = link.clone() {
+                            region_ranges.push(segment_start..text.len());
+                            regions.push(ParsedRegion {
+                                code: false,
+                                link: Some(link),
+                                abbreviation: None,
+                            });
+                            style.underline = true;
+                        } else {
+                            for (range, expansion) in self.abbreviations_in(&text[segment_start..])
+                            {
+                                region_ranges
+                                    .push(segment_start + range.start..segment_start + range.end);
+                                regions.push(ParsedRegion {
+                                    code: false,
+                                    link: None,
+                                    abbreviation: Some(expansion),
+                                });
                             }
                         }
-                        if new_highlight {
-                            let range = prev_len..text.len();
-                            highlights.push((range, MarkdownHighlight::Style(style)));
+
+                        if style != MarkdownHighlightStyle::default() {
+                            let mut new_highlight = true;
+                            if let Some((last_range, MarkdownHighlight::Style(last_style))) =
+                                highlights.last_mut()
+                            {
+                                if last_range.end == segment_start && last_style == &style {
+                                    last_range.end = text.len();
+                                    new_highlight = false;
+                                }
+                            }
+                            if new_highlight {
+                                let range = segment_start..text.len();
+                                highlights.push((range, MarkdownHighlight::Style(style)));
+                            }
                         }
                     }
                 }
 
+                Event::FootnoteReference(label) => {
+                    // Numbered footnotes are shown as superscripts, as they're usually
+                    // typeset, while named ones stay readable in brackets.
+                    if label.chars().all(|c| c.is_ascii_digit()) {
+                        text.push_str(&superscript(label).unwrap_or_else(|| label.to_string()));
+                    } else {
+                        text.push_str(&format!("[{label}]"));
+                    }
+
+                    let link = match self.footnotes.get(label.as_ref()) {
+                        Some(footnote) => Link::Footnote {
+                            label: label.to_string(),
+                            text: footnote.clone(),
+                        },
+                        None => Link::Broken {
+                            destination: format!("[^{label}]"),
+                            reason: BrokenLinkReason::FootnoteNotFound,
+                        },
+                    };
+                    region_ranges.push(prev_len..text.len());
+                    regions.push(ParsedRegion {
+                        code: false,
+                        link: Some(link),
+                        abbreviation: None,
+                    });
+                }
+
                 // Note: This event means "inline code" and not "code block"
                 Event::Code(t) => {
-                    text.push_str(t.as_ref());
+                    if self.in_table_cell {
+                        text.push_str(&t.replace("\\|", "|"));
+                    } else {
+                        text.push_str(t.as_ref());
+                    }
                     region_ranges.push(prev_len..text.len());
 
                     if link.is_some() {
@@ -245,6 +651,7 @@ impl<'a> MarkdownParser<'a> {
                     regions.push(ParsedRegion {
                         code: true,
                         link: link.clone(),
+                        abbreviation: None,
                     });
                 }
 
@@ -252,14 +659,32 @@ impl<'a> MarkdownParser<'a> {
                     match tag {
                         Tag::Emphasis => italic_depth += 1,
                         Tag::Strong => bold_depth += 1,
+                        // Reference-style links and images are resolved by pulldown-cmark,
+                        // so they arrive here with their destination already filled in.
                         Tag::Link(_type, url, _title) => {
-                            link = Link::identify(
-                                self.file_location_directory.clone(),
-                                url.to_string(),
-                            );
+                            link = self.identify_link(url);
+                        }
+                        // Images are parsed as their alt text, linking to the image,
+                        // for when the image itself can't be shown.
+                        Tag::Image(_type, url, _title) => {
+                            link = self.identify_link(url);
+                            image = MarkdownImageSource::identify(
+                                self.file_location_directory.as_deref(),
+                                url,
+                            )
+                            .map(|source| (source, text.len()));
                         }
                         Tag::Strikethrough => {
-                            // TODO: Confirm that gpui currently doesn't support strikethroughs
+                            // `~text~` is a subscript, while `~~text~~` is a strikethrough.
+                            let is_subscript = self.peek(1).map_or(false, |(_, next_range)| {
+                                next_range.start == current_range.start + 1
+                            });
+                            if is_subscript {
+                                subscript_depth += 1;
+                            } else {
+                                strikethrough_depth += 1;
+                            }
+                            strikethrough_kinds.push(is_subscript);
                         }
                         _ => {
                             break;
@@ -277,8 +702,23 @@ impl<'a> MarkdownParser<'a> {
                     Tag::Link(_, _, _) => {
                         link = None;
                     }
+                    Tag::Image(_, _, _) => {
+                        link = None;
+                        if let Some((source, alt_text_start)) = image.take() {
+                            images.push(ParsedMarkdownImage {
+                                source_range: current_range.clone(),
+                                alt_text_range: alt_text_start..text.len(),
+                                source,
+                                loaded: None,
+                            });
+                        }
+                    }
                     Tag::Strikethrough => {
-                        // TODO: Confirm that gpui currently doesn't support strikethroughs
+                        if strikethrough_kinds.pop() == Some(true) {
+                            subscript_depth -= 1;
+                        } else {
+                            strikethrough_depth -= 1;
+                        }
                     }
                     Tag::Paragraph => {
                         self.cursor += 1;
@@ -303,6 +743,7 @@ impl<'a> MarkdownParser<'a> {
             highlights,
             regions,
             region_ranges,
+            images,
         }
     }
 
@@ -351,7 +792,9 @@ impl<'a> MarkdownParser<'a> {
                 }
                 Event::Start(Tag::TableCell) => {
                     self.cursor += 1;
+                    self.in_table_cell = true;
                     let cell_contents = self.parse_text(false);
+                    self.in_table_cell = false;
                     current_row.push(cell_contents);
                 }
                 Event::End(Tag::TableHead) | Event::End(Tag::TableRow) => {
@@ -384,9 +827,33 @@ impl<'a> MarkdownParser<'a> {
             header,
             body,
             column_alignments: alignment,
+            caption: self.parse_table_caption(),
         }
     }
 
+    /// Parses the caption of the table that was just parsed, written as a paragraph
+    /// after it that starts with `Table:` or `:`, as in Pandoc.
+    fn parse_table_caption(&mut self) -> Option<ParsedMarkdownText> {
+        let (Event::Start(Tag::Paragraph), _) = self.current()? else {
+            return None;
+        };
+        let (Event::Text(text), range) = self.peek(1)? else {
+            return None;
+        };
+        let caption = ["Table:", ":"]
+            .iter()
+            .find_map(|prefix| text.strip_prefix(prefix))
+            .filter(|caption| caption.is_empty() || caption.starts_with(char::is_whitespace))?
+            .trim_start();
+
+        // Drop the prefix from the caption's first text, so it isn't rendered.
+        let caption_range = range.end - caption.len()..range.end;
+        let caption = Event::Text(CowStr::from(caption.to_string()));
+        self.tokens[self.cursor + 1] = (caption, caption_range);
+        self.cursor += 1;
+        Some(self.parse_text(false))
+    }
+
     fn convert_alignment(alignment: &Alignment) -> ParsedMarkdownTableAlignment {
         match alignment {
             Alignment::None => ParsedMarkdownTableAlignment::None,
@@ -482,6 +949,17 @@ impl<'a> MarkdownParser<'a> {
                         break;
                     }
 
+                    // Line breaks, soft or hard, and with or without `hard_line_breaks`,
+                    // are kept within the item's text. So text only gets here after
+                    // another block in a tight list item, such as a code block, and
+                    // continues the item as another paragraph.
+                    if MarkdownParser::is_text_like(current) {
+                        let text = self.parse_text(false);
+                        let block = ParsedMarkdownElement::Paragraph(text);
+                        current_list_items.push(Box::new(block));
+                        continue;
+                    }
+
                     let block = self.parse_block();
                     if let Some(block) = block {
                         current_list_items.push(Box::new(block));
@@ -542,7 +1020,27 @@ impl<'a> MarkdownParser<'a> {
         }
     }
 
-    fn parse_code_block(&mut self, language: Option<String>) -> ParsedMarkdownCodeBlock {
+    /// Splits a fence info string such as `rust title="main.rs"`
+    /// into the block's language and its title attribute.
+    fn parse_fence_info(info: &str) -> FenceInfo {
+        let info = info.trim();
+        let (language, attributes) = info.split_once(char::is_whitespace).unwrap_or((info, ""));
+        let (language, attributes) = if language.contains('=') {
+            (None, info)
+        } else if language.is_empty() {
+            (None, attributes)
+        } else {
+            (Some(language.to_string()), attributes)
+        };
+
+        FenceInfo {
+            language,
+            title: fence_attribute(attributes, "title"),
+            theme: fence_attribute(attributes, "theme"),
+        }
+    }
+
+    fn parse_code_block(&mut self, fence: FenceInfo) -> ParsedMarkdownCodeBlock {
         let (_event, source_range) = self.previous().unwrap();
         let source_range = source_range.clone();
         let mut code = String::new();
@@ -564,12 +1062,313 @@ impl<'a> MarkdownParser<'a> {
             }
         }
 
+        let language = fence
+            .language
+            .or_else(|| detect_language(fence.title.as_deref(), &code));
         ParsedMarkdownCodeBlock {
             source_range,
             contents: code.trim().to_string().into(),
             language,
+            language_name: None,
+            title: fence.title,
+            theme: fence.theme,
+            highlights: Vec::new(),
+            diagram: None,
+        }
+    }
+}
+
+/// The language and attributes of a fenced code block, parsed from its info string,
+/// e.g. ```` ```rust title=main.rs theme="One Light" ````.
+#[derive(Default)]
+struct FenceInfo {
+    language: Option<String>,
+    title: Option<String>,
+    theme: Option<String>,
+}
+
+/// Parses an abbreviation definition, such as `*[HTML]: HyperText Markup Language`.
+fn abbreviation_definition(line: &str) -> Option<(&str, &str)> {
+    let (abbreviation, expansion) = line.trim().strip_prefix("*[")?.split_once("]:")?;
+    let expansion = expansion.trim();
+    (!abbreviation.is_empty() && !expansion.is_empty()).then_some((abbreviation, expansion))
+}
+
+/// Finds the value of an attribute in a fence's info string, which may be quoted.
+/// Only whole names match, so `title` isn't found in `subtitle="A title"`.
+fn fence_attribute(attributes: &str, name: &str) -> Option<String> {
+    let mut rest = attributes;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        rest = &rest[key_end..];
+
+        // Words without a value, such as a language after the attributes, are skipped.
+        let Some(value) = rest.strip_prefix('=') else {
+            continue;
+        };
+        let (value, remaining) = if let Some(quoted) = value.strip_prefix('"') {
+            quoted.split_once('"').unwrap_or((quoted, ""))
+        } else {
+            value.split_once(char::is_whitespace).unwrap_or((value, ""))
+        };
+        rest = remaining;
+
+        if key == name {
+            return (!value.is_empty()).then(|| value.to_string());
+        }
+    }
+}
+
+/// Guesses the language of a code block whose fence doesn't name one, so that it
+/// can still be highlighted: from the extension of its title, from a shebang,
+/// or from a telltale first line. The result is a language name or file extension.
+fn detect_language(title: Option<&str>, code: &str) -> Option<String> {
+    let extension = title
+        .and_then(|title| Path::new(title).extension())
+        .and_then(|extension| extension.to_str());
+    if let Some(extension) = extension {
+        return Some(extension.to_string());
+    }
+
+    let first_line = code.trim_start().lines().next()?.trim();
+    if let Some(shebang) = first_line.strip_prefix("#!") {
+        // Both `#!/bin/bash` and `#!/usr/bin/env python3` name the interpreter.
+        let mut words = shebang.split_whitespace();
+        let program = words.next()?.rsplit('/').next()?;
+        let interpreter = if program == "env" {
+            words.find(|word| !word.starts_with('-'))?
+        } else {
+            program
+        };
+        let language = match interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.')
+        {
+            "sh" | "bash" | "zsh" | "dash" | "ksh" => "sh",
+            "python" => "py",
+            "node" | "deno" | "bun" => "js",
+            "ruby" => "rb",
+            "perl" => "pl",
+            interpreter => interpreter,
+        };
+        return Some(language.to_string());
+    }
+
+    let first_line = first_line.to_lowercase();
+    let language = if first_line.starts_with("<?php") {
+        "php"
+    } else if first_line.starts_with("<!doctype html") || first_line.starts_with("<html") {
+        "html"
+    } else if first_line.starts_with("<?xml") {
+        "xml"
+    } else if first_line.starts_with("diff --git") || first_line.starts_with("--- a/") {
+        "diff"
+    } else if code
+        .trim_start()
+        .strip_prefix('{')
+        .map_or(false, |object| object.trim_start().starts_with('"'))
+    {
+        "json"
+    } else {
+        return None;
+    };
+    Some(language.to_string())
+}
+
+/// Converts heading text into the anchor that links to it, following GitHub's
+/// convention: `## Getting Started!` can be linked to with `#getting-started`.
+fn heading_anchor(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                Some(c)
+            } else if c.is_whitespace() {
+                Some('-')
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// An extended Markdown inline style that pulldown-cmark doesn't parse itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InlineExtension {
+    None,
+    /// `==highlighted==`
+    Highlight,
+    /// `^superscript^`
+    Superscript,
+    /// `~subscript~`
+    Subscript,
+}
+
+/// Splits a run of text into segments, stripping the delimiters of any
+/// `==highlight==`, `^superscript^` or `~subscript~` spans it contains.
+fn parse_inline_extensions(text: &str) -> Vec<(&str, InlineExtension)> {
+    let mut segments = vec![];
+    let mut plain_start = 0;
+    let mut ix = 0;
+
+    while ix < text.len() {
+        let rest = &text[ix..];
+        let span = if rest.starts_with("==") {
+            rest[2..].find("==").and_then(|end| {
+                let contents = &rest[2..2 + end];
+                let is_valid = !contents.is_empty()
+                    && !contents.starts_with(char::is_whitespace)
+                    && !contents.ends_with(char::is_whitespace);
+                is_valid.then_some((contents, InlineExtension::Highlight, end + 4))
+            })
+        } else if rest.starts_with(['^', '~']) {
+            let delimiter = &rest[..1];
+            rest[1..].find(delimiter).and_then(|end| {
+                let contents = &rest[1..1 + end];
+                let is_valid = !contents.is_empty() && !contents.contains(char::is_whitespace);
+                let extension = if delimiter == "^" {
+                    InlineExtension::Superscript
+                } else {
+                    InlineExtension::Subscript
+                };
+                is_valid.then_some((contents, extension, end + 2))
+            })
+        } else {
+            None
+        };
+
+        match span {
+            Some((contents, extension, len)) => {
+                if plain_start < ix {
+                    segments.push((&text[plain_start..ix], InlineExtension::None));
+                }
+                segments.push((contents, extension));
+                ix += len;
+                plain_start = ix;
+            }
+            None => ix += rest.chars().next().map_or(1, char::len_utf8),
         }
     }
+
+    if plain_start < text.len() || segments.is_empty() {
+        segments.push((&text[plain_start..], InlineExtension::None));
+    }
+
+    segments
+}
+
+/// Converts text to Unicode superscript characters, or returns `None`
+/// if any of its characters has no superscript form.
+fn superscript(text: &str) -> Option<String> {
+    text.chars()
+        .map(|c| {
+            Some(match c {
+                '0' => '⁰',
+                '1' => '¹',
+                '2' => '²',
+                '3' => '³',
+                '4' => '⁴',
+                '5' => '⁵',
+                '6' => '⁶',
+                '7' => '⁷',
+                '8' => '⁸',
+                '9' => '⁹',
+                '+' => '⁺',
+                '-' => '⁻',
+                '=' => '⁼',
+                '(' => '⁽',
+                ')' => '⁾',
+                'a' => 'ᵃ',
+                'b' => 'ᵇ',
+                'c' => 'ᶜ',
+                'd' => 'ᵈ',
+                'e' => 'ᵉ',
+                'f' => 'ᶠ',
+                'g' => 'ᵍ',
+                'h' => 'ʰ',
+                'i' => 'ⁱ',
+                'j' => 'ʲ',
+                'k' => 'ᵏ',
+                'l' => 'ˡ',
+                'm' => 'ᵐ',
+                'n' => 'ⁿ',
+                'o' => 'ᵒ',
+                'p' => 'ᵖ',
+                'r' => 'ʳ',
+                's' => 'ˢ',
+                't' => 'ᵗ',
+                'u' => 'ᵘ',
+                'v' => 'ᵛ',
+                'w' => 'ʷ',
+                'x' => 'ˣ',
+                'y' => 'ʸ',
+                'z' => 'ᶻ',
+                _ => return None,
+            })
+        })
+        .collect()
+}
+
+/// Converts text to Unicode subscript characters, or returns `None`
+/// if any of its characters has no subscript form.
+fn subscript(text: &str) -> Option<String> {
+    text.chars()
+        .map(|c| {
+            Some(match c {
+                '0' => '₀',
+                '1' => '₁',
+                '2' => '₂',
+                '3' => '₃',
+                '4' => '₄',
+                '5' => '₅',
+                '6' => '₆',
+                '7' => '₇',
+                '8' => '₈',
+                '9' => '₉',
+                '+' => '₊',
+                '-' => '₋',
+                '=' => '₌',
+                '(' => '₍',
+                ')' => '₎',
+                'a' => 'ₐ',
+                'e' => 'ₑ',
+                'h' => 'ₕ',
+                'i' => 'ᵢ',
+                'j' => 'ⱼ',
+                'k' => 'ₖ',
+                'l' => 'ₗ',
+                'm' => 'ₘ',
+                'n' => 'ₙ',
+                'o' => 'ₒ',
+                'p' => 'ₚ',
+                'r' => 'ᵣ',
+                's' => 'ₛ',
+                't' => 'ₜ',
+                'u' => 'ᵤ',
+                'v' => 'ᵥ',
+                'x' => 'ₓ',
+                _ => return None,
+            })
+        })
+        .collect()
+}
+
+/// Whether an inline HTML tag is a line break, such as `<br>` or `<br />`.
+fn is_line_break_tag(html: &str) -> bool {
+    let Some(tag) = html
+        .strip_prefix('<')
+        .and_then(|html| html.strip_suffix('>'))
+    else {
+        return false;
+    };
+    tag.trim_end_matches('/').trim().eq_ignore_ascii_case("br")
 }
 
 #[cfg(test)]
@@ -582,7 +1381,7 @@ mod tests {
     use ParsedMarkdownListItemType::*;
 
     fn parse(input: &str) -> ParsedMarkdown {
-        parse_markdown(input, None)
+        parse_markdown(input, None, &[], false, false)
     }
 
     #[test]
@@ -642,6 +1441,348 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_extended_inline_styles() {
+        let parsed = parse("~~gone~~ ==marked== x^2^ H~2~O and 1 == 1 ^ 0 Zed^TM^");
+
+        assert_eq!(
+            parsed.children,
+            vec![p("gone marked x² H₂O and 1 == 1 ^ 0 ZedTM", 0..53)]
+        );
+
+        let ParsedMarkdownElement::Paragraph(text) = &parsed.children[0] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(
+            text.highlights,
+            vec![
+                (
+                    0..4,
+                    MarkdownHighlight::Style(MarkdownHighlightStyle {
+                        strikethrough: true,
+                        ..Default::default()
+                    })
+                ),
+                (
+                    5..11,
+                    MarkdownHighlight::Style(MarkdownHighlightStyle {
+                        highlight: true,
+                        ..Default::default()
+                    })
+                ),
+                (
+                    40..42,
+                    MarkdownHighlight::Style(MarkdownHighlightStyle {
+                        script_fallback: true,
+                        ..Default::default()
+                    })
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_smart_typography() {
+        let input = "\"Quotes\" -- it's --- done...";
+
+        assert_eq!(
+            parse_markdown(input, None, &[], true, false).children,
+            vec![p("“Quotes” – it’s — done…", 0..28)]
+        );
+        assert_eq!(parse(input).children, vec![p(input, 0..28)]);
+    }
+
+    #[test]
+    fn test_html_comments_and_line_breaks() {
+        let parsed = parse(
+            "\
+Hello <!-- hidden
+still hidden --> world<br>next
+line  
+hard
+
+<!-- block comment -->
+
+after",
+        );
+        assert_eq!(
+            parsed.children,
+            vec![
+                p("Hello  world\nnext line\nhard", 0..61),
+                p("after", 86..91)
+            ]
+        );
+
+        let input = "first\nsecond";
+        assert_eq!(
+            parse_markdown(input, None, &[], false, true).children,
+            vec![p("first\nsecond", 0..12)]
+        );
+        assert_eq!(parse(input).children, vec![p("first second", 0..12)]);
+    }
+
+    #[test]
+    fn test_reference_links_and_images() {
+        let parsed = parse(
+            "\
+See [the docs][docs], [Zed][] and ![the logo][logo] here.
+
+[docs]: https://docs.rs
+[zed]: https://zed.dev
+[logo]: https://zed.dev/logo.png",
+        );
+
+        assert_eq!(
+            parsed.children,
+            vec![p("See the docs, Zed and the logo here.", 0..58)]
+        );
+
+        let ParsedMarkdownElement::Paragraph(text) = &parsed.children[0] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(text.region_ranges, vec![4..12, 14..17, 22..30]);
+        assert_eq!(
+            text.regions
+                .iter()
+                .map(|region| region.link.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Some(Link::Web {
+                    url: "https://docs.rs".to_string()
+                }),
+                Some(Link::Web {
+                    url: "https://zed.dev".to_string()
+                }),
+                Some(Link::Web {
+                    url: "https://zed.dev/logo.png".to_string()
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_broken_links() {
+        let input = "\
+# Getting Started!
+
+[intro](#getting-started) [missing](#nowhere) [file](./missing.md) [mail](mailto:a@b.c)";
+        let parsed = parse_markdown(
+            input,
+            Some(PathBuf::from("/nonexistent-markdown-preview-dir")),
+            &[],
+            false,
+            false,
+        );
+
+        let ParsedMarkdownElement::Paragraph(text) = &parsed.children[1] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(
+            text.regions
+                .iter()
+                .map(|region| region.link.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Some(Link::Heading {
+                    anchor: "getting-started".to_string(),
+                    section: input.into(),
+                }),
+                Some(Link::Broken {
+                    destination: "#nowhere".to_string(),
+                    reason: BrokenLinkReason::HeadingNotFound,
+                }),
+                Some(Link::Broken {
+                    destination: "./missing.md".to_string(),
+                    reason: BrokenLinkReason::FileNotFound,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_footnotes_and_heading_sections() {
+        let parsed = parse(
+            "\
+Text[^1], [^note] and [^missing], see [sub](#sub).
+
+[^1]: The *first*
+    note.
+
+[^note]: Another.
+
+# Heading
+
+## Sub
+
+Body.
+
+# Next",
+        );
+
+        let ParsedMarkdownElement::Paragraph(text) = &parsed.children[0] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(text.contents, "Text¹, [note] and [missing], see sub.");
+        assert_eq!(
+            text.regions
+                .iter()
+                .map(|region| region.link.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Some(Link::Footnote {
+                    label: "1".to_string(),
+                    text: "The *first*\nnote.".into(),
+                }),
+                Some(Link::Footnote {
+                    label: "note".to_string(),
+                    text: "Another.".into(),
+                }),
+                Some(Link::Broken {
+                    destination: "[^missing]".to_string(),
+                    reason: BrokenLinkReason::FootnoteNotFound,
+                }),
+                Some(Link::Heading {
+                    anchor: "sub".to_string(),
+                    section: "## Sub\n\nBody.".into(),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_abbreviations() {
+        let parsed = parse(
+            "\
+The HTML5 and HTML specs, by the W3C.
+
+*[HTML]: HyperText Markup Language
+*[W3C]:  World Wide Web Consortium",
+        );
+
+        assert_eq!(parsed.children.len(), 1);
+        let ParsedMarkdownElement::Paragraph(text) = &parsed.children[0] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(text.region_ranges, vec![14..18, 33..36]);
+        assert_eq!(
+            text.regions
+                .iter()
+                .map(|region| region.abbreviation.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Some("HyperText Markup Language".into()),
+                Some("World Wide Web Consortium".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wiki_links() {
+        let markdown_files = [
+            PathBuf::from("/vault/Page Name.md"),
+            PathBuf::from("/vault/notes/daily-log.md"),
+        ];
+        let parsed = parse_markdown(
+            "See [[page name]], [[notes/daily-log|the log]] and [[Missing]].",
+            None,
+            &markdown_files,
+            false,
+            false,
+        );
+
+        let ParsedMarkdownElement::Paragraph(text) = &parsed.children[0] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(text.contents, "See page name, the log and Missing.");
+        assert_eq!(text.region_ranges, vec![4..13, 15..22, 27..34]);
+        assert_eq!(
+            text.regions
+                .iter()
+                .map(|region| region.link.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Some(Link::Path {
+                    path: PathBuf::from("/vault/Page Name.md"),
+                }),
+                Some(Link::Path {
+                    path: PathBuf::from("/vault/notes/daily-log.md"),
+                }),
+                Some(Link::Broken {
+                    destination: "Missing".to_string(),
+                    reason: BrokenLinkReason::FileNotFound,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_links_to_files_with_encoded_names_and_queries() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("my notes.md");
+        std::fs::write(&path, "").unwrap();
+
+        for destination in [
+            "my%20notes.md",
+            "my%20notes.md?plain=1#intro",
+            "my notes.md?v=2",
+        ] {
+            assert_eq!(
+                Link::identify(
+                    Some(directory.path().to_path_buf()),
+                    destination.to_string()
+                ),
+                Some(Link::Path { path: path.clone() }),
+                "{destination}"
+            );
+        }
+        assert_eq!(
+            Link::identify(
+                Some(directory.path().to_path_buf()),
+                "other%20notes.md".to_string()
+            ),
+            Some(Link::Broken {
+                destination: "other%20notes.md".to_string(),
+                reason: BrokenLinkReason::FileNotFound,
+            })
+        );
+    }
+
+    #[test]
+    fn test_images() {
+        let badge = "data:image/svg+xml;base64,PHN2Zz48L3N2Zz4=";
+        let parsed = parse(&format!(
+            "![build]({badge}) ![missing](./missing.png)\n\nSee ![badge]({badge}) here."
+        ));
+
+        let ParsedMarkdownElement::Paragraph(badges) = &parsed.children[0] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(badges.contents, "build missing");
+        assert_eq!(
+            badges
+                .images
+                .iter()
+                .map(|image| (image.alt_text_range.clone(), image.source.clone()))
+                .collect::<Vec<_>>(),
+            vec![(0..5, MarkdownImageSource::DataUri(badge.to_string()))]
+        );
+        assert!(!badges.is_only_images());
+
+        let ParsedMarkdownElement::Paragraph(text) = &parsed.children[1] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(text.contents, "See badge here.");
+        assert_eq!(text.images.len(), 1);
+        assert_eq!(text.images[0].alt_text_range, 4..9);
+        assert!(!text.is_only_images());
+
+        let only_images = parse(&format!("![a]({badge}) ![b]({badge})"));
+        let ParsedMarkdownElement::Paragraph(text) = &only_images.children[0] else {
+            panic!("expected a paragraph");
+        };
+        assert!(text.is_only_images());
+    }
+
     #[test]
     fn test_header_only_table() {
         let markdown = "\
@@ -686,6 +1827,29 @@ Some other content
         );
     }
 
+    #[test]
+    fn test_table_with_inline_markdown_and_caption() {
+        let markdown = "\
+| Code | Link |
+|------|------|
+| `a \\| b` **bold** | [zed](https://zed.dev) |
+
+Table: Some *caption*";
+
+        let ParsedMarkdownElement::Table(table) = &parse(markdown).children[0] else {
+            panic!("expected a table");
+        };
+        assert_eq!(table.body[0].children[0].contents, "a | b bold");
+        assert_eq!(
+            table.body[0].children[1].regions[0].link,
+            Some(Link::Web {
+                url: "https://zed.dev".to_string()
+            })
+        );
+        assert_eq!(table.caption, Some(text("Some caption", 80..101)));
+        assert_eq!(parse(markdown).children.len(), 1);
+    }
+
     #[test]
     fn test_list_basic() {
         let parsed = parse(
@@ -730,6 +1894,69 @@ Some other content
         );
     }
 
+    #[test]
+    fn test_task_progress() {
+        let parsed = parse(
+            "\
+- [x] Done
+- [ ] Not done
+  - [x] Nested and done
+  - Not a task
+",
+        );
+
+        let ParsedMarkdownElement::List(list) = &parsed.children[0] else {
+            panic!("expected a list");
+        };
+        assert_eq!(list.task_progress(), (2, 3));
+    }
+
+    #[test]
+    fn test_right_to_left_text() {
+        let parsed = parse(
+            "\
+# שלום
+
+123 مرحبا بالعالم
+
+Zed يدعم العربية
+
+- עברית
+- English
+
+| Name | الاسم |
+|------|-------|
+",
+        );
+
+        let directions: Vec<bool> = parsed.children[..3]
+            .iter()
+            .map(|block| block.is_right_to_left())
+            .collect();
+        assert_eq!(directions, vec![true, true, false]);
+
+        let ParsedMarkdownElement::List(list) = &parsed.children[3] else {
+            panic!("expected a list");
+        };
+        let directions: Vec<bool> = list
+            .children
+            .iter()
+            .map(|item| item.contents[0].is_right_to_left())
+            .collect();
+        assert_eq!(directions, vec![true, false]);
+
+        let ParsedMarkdownElement::Table(table) = &parsed.children[4] else {
+            panic!("expected a table");
+        };
+        let directions: Vec<bool> = table
+            .header
+            .children
+            .iter()
+            .map(|cell| cell.is_right_to_left())
+            .collect();
+        assert_eq!(directions, vec![false, true]);
+    }
+
     #[test]
     fn test_list_nested() {
         let parsed = parse(
@@ -864,6 +2091,68 @@ Some other content
         );
     }
 
+    #[test]
+    fn test_list_with_nested_blocks() {
+        let parsed = parse(
+            "\
+3. First item
+
+   ```rust
+   code
+   ```
+
+   > quote
+4. Second line\\
+   after a break
+   - nested
+     1. deeply nested
+",
+        );
+
+        assert_eq!(
+            parsed.children,
+            vec![list(
+                vec![
+                    list_item(
+                        1,
+                        Ordered(3),
+                        vec![
+                            p("First item", 3..14),
+                            code_block(Some("rust".into()), "code", 18..40),
+                            block_quote(vec![p("quote", 47..53)], 45..53),
+                        ],
+                    ),
+                    list_item(
+                        1,
+                        Ordered(4),
+                        vec![
+                            p("Second line\nafter a break", 56..86),
+                            list(
+                                vec![list_item(
+                                    2,
+                                    Unordered,
+                                    vec![
+                                        p("nested", 89..120),
+                                        list(
+                                            vec![list_item(
+                                                3,
+                                                Ordered(1),
+                                                vec![p("deeply nested", 103..120)],
+                                            )],
+                                            103..120,
+                                        ),
+                                    ],
+                                )],
+                                89..120,
+                            ),
+                        ],
+                    ),
+                ],
+                0..120,
+            )]
+        );
+    }
+
     #[test]
     fn test_list_with_leading_text() {
         let parsed = parse(
@@ -955,6 +2244,41 @@ More text
         );
     }
 
+    #[test]
+    fn test_splicing_reparsed_blocks() {
+        let mut contents =
+            parse("# Title\n\nFirst paragraph.\n\n- one\n- two\n\nLast paragraph.\n");
+
+        // Replacing "Last" with "Final" re-parses the edited paragraph
+        // along with the list before it.
+        let new_text = "# Title\n\nFirst paragraph.\n\n- one\n- two\n\nFinal paragraph.\n";
+        let region = new_text.find("- one").unwrap()..new_text.len();
+        assert!(can_parse_in_isolation(&new_text[region.clone()]));
+
+        let mut blocks = parse(&new_text[region.clone()]);
+        for block in &mut blocks.children {
+            block.offset_source_ranges(region.start as isize);
+        }
+        contents.splice(2..4, blocks.children, 1);
+
+        assert_eq!(contents.children, parse(new_text).children);
+    }
+
+    #[test]
+    fn test_parsing_in_isolation() {
+        assert!(can_parse_in_isolation(
+            "Some *text* and a [[wiki link]].\n\n- [ ] task"
+        ));
+        assert!(!can_parse_in_isolation("[zed]: https://zed.dev"));
+        assert!(!can_parse_in_isolation("Text[^1]\n\n[^1]: A footnote"));
+        assert!(!can_parse_in_isolation(
+            "*[HTML]: HyperText Markup Language"
+        ));
+        assert!(!can_parse_in_isolation("See [the title](#title)"));
+        assert!(!can_parse_in_isolation("```rust\nfn main() {}"));
+        assert!(!can_parse_in_isolation("<!-- a comment"));
+    }
+
     #[test]
     fn test_code_block() {
         let parsed = parse(
@@ -995,6 +2319,93 @@ fn main() {
         );
     }
 
+    #[test]
+    fn test_code_block_with_title() {
+        let parsed = parse(
+            "\
+```rust title=main.rs
+fn main() {}
+```
+
+```title=\"Hello world.py\" python
+print()
+```
+
+```js subtitle=\"Not the title\" title=app.js
+run()
+```
+
+```text subtitle=notes.txt
+notes
+```
+",
+        );
+
+        assert_eq!(
+            parsed.children,
+            vec![
+                code_block_with_title(
+                    Some("rust".into()),
+                    Some("main.rs".into()),
+                    "fn main() {}",
+                    0..38
+                ),
+                code_block_with_title(
+                    Some("py".into()),
+                    Some("Hello world.py".into()),
+                    "print()",
+                    40..84
+                ),
+                code_block_with_title(Some("js".into()), Some("app.js".into()), "run()", 86..139),
+                code_block_with_title(Some("text".into()), None, "notes", 141..177),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_code_block_language_detection() {
+        let parsed = parse(
+            "\
+```
+#!/usr/bin/env python3
+```
+
+```
+{
+  \"key\": 1
+}
+```
+
+```title=script.rs theme=\"One Light\"
+fn main() {}
+```
+
+```
+plain text
+```
+",
+        );
+
+        assert_eq!(
+            parsed
+                .children
+                .iter()
+                .map(|block| match block {
+                    ParsedMarkdownElement::CodeBlock(code_block) => {
+                        (code_block.language.clone(), code_block.theme.clone())
+                    }
+                    _ => panic!("expected a code block"),
+                })
+                .collect::<Vec<_>>(),
+            vec![
+                (Some("py".into()), None),
+                (Some("json".into()), None),
+                (Some("rs".into()), Some("One Light".into())),
+                (None, None),
+            ]
+        );
+    }
+
     fn h1(contents: ParsedMarkdownText, source_range: Range<usize>) -> ParsedMarkdownElement {
         ParsedMarkdownElement::Heading(ParsedMarkdownHeading {
             source_range,
@@ -1028,6 +2439,7 @@ fn main() {
             highlights: Vec::new(),
             region_ranges: Vec::new(),
             regions: Vec::new(),
+            images: Vec::new(),
             source_range,
             contents: contents.to_string(),
         }
@@ -1047,11 +2459,25 @@ fn main() {
         language: Option<String>,
         code: &str,
         source_range: Range<usize>,
+    ) -> ParsedMarkdownElement {
+        code_block_with_title(language, None, code, source_range)
+    }
+
+    fn code_block_with_title(
+        language: Option<String>,
+        title: Option<String>,
+        code: &str,
+        source_range: Range<usize>,
     ) -> ParsedMarkdownElement {
         ParsedMarkdownElement::CodeBlock(ParsedMarkdownCodeBlock {
             source_range,
             language,
+            language_name: None,
+            title,
+            theme: None,
             contents: code.to_string().into(),
+            highlights: Vec::new(),
+            diagram: None,
         })
     }
 
@@ -1087,6 +2513,7 @@ fn main() {
             source_range,
             header,
             body,
+            caption: None,
         }
     }
 
@@ -1099,6 +2526,13 @@ fn main() {
             self.source_range == other.source_range
                 && self.header == other.header
                 && self.body == other.body
+                && self.caption == other.caption
+        }
+    }
+
+    impl PartialEq for LoadedMarkdownImage {
+        fn eq(&self, other: &Self) -> bool {
+            std::sync::Arc::ptr_eq(&self.data, &other.data)
         }
     }
 
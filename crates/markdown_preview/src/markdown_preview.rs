@@ -1,14 +1,56 @@
 use gpui::{actions, AppContext};
+use settings::{Settings, SettingsStore};
 use workspace::Workspace;
 
+use crate::markdown_preview_settings::MarkdownPreviewSettings;
+
 pub mod markdown_elements;
+mod markdown_images;
 pub mod markdown_parser;
+pub mod markdown_preview_settings;
+mod markdown_preview_toolbar;
 pub mod markdown_preview_view;
 pub mod markdown_renderer;
+mod persistence;
+
+pub use markdown_preview_toolbar::MarkdownPreviewToolbar;
 
-actions!(markdown, [OpenPreview]);
+actions!(
+    markdown,
+    [
+        OpenPreview,
+        OpenPreviewToTheRight,
+        OpenPreviewBelow,
+        OpenPreviewInCurrentPane,
+        OpenPreviewInNewWindow,
+        OpenPreviewForBuffer,
+        PasteImage,
+        TogglePreview,
+        TogglePreviewPin,
+        ToggleSource,
+        ToggleSplit,
+        Refresh,
+        ScrollPageUp,
+        ScrollPageDown,
+        ScrollToTop,
+        ScrollToBottom,
+        NextHeading,
+        PreviousHeading,
+        ZoomIn,
+        ZoomOut,
+        ResetZoom
+    ]
+);
 
 pub fn init(cx: &mut AppContext) {
+    MarkdownPreviewSettings::register(cx);
+    cx.observe_global::<SettingsStore>(|cx| {
+        MarkdownPreviewSettings::get_global(cx).log_unknown_themes(cx);
+    })
+    .detach();
+    markdown_preview_view::load_last_placement(cx);
+    workspace::register_deserializable_item::<markdown_preview_view::MarkdownPreviewView>(cx);
+
     cx.observe_new_views(|workspace: &mut Workspace, cx| {
         markdown_preview_view::MarkdownPreviewView::register(workspace, cx);
     })
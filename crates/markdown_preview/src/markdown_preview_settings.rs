@@ -0,0 +1,280 @@
+use gpui::{AppContext, Hsla, Pixels};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use settings::Settings;
+use std::sync::Arc;
+use theme::{ActiveTheme, SyntaxTheme, Theme, ThemeRegistry};
+use util::ResultExt;
+
+use crate::markdown_elements::HeadingLevel;
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewPlacement {
+    /// Split the active pane and show the preview to the right of it.
+    #[default]
+    Right,
+    /// Split the active pane and show the preview below it.
+    Below,
+    /// Open the preview as a new tab in the active pane.
+    CurrentPane,
+    /// Open the preview in a new window.
+    NewWindow,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewContentWidth {
+    /// Let the content span the full width of the preview.
+    FullWidth,
+    /// Limit the content to the given width in pixels, centering it in the preview.
+    MaxWidth(f32),
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewRefreshMode {
+    /// Re-render the preview shortly after the document is edited.
+    #[default]
+    Automatic,
+    /// Only re-render the preview when it is explicitly refreshed.
+    Manual,
+}
+
+/// How large each level of heading is, relative to the font size of body text.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct HeadingScale {
+    pub h1: f32,
+    pub h2: f32,
+    pub h3: f32,
+    pub h4: f32,
+    pub h5: f32,
+    pub h6: f32,
+}
+
+impl HeadingScale {
+    pub fn for_level(&self, level: &HeadingLevel) -> f32 {
+        match level {
+            HeadingLevel::H1 => self.h1,
+            HeadingLevel::H2 => self.h2,
+            HeadingLevel::H3 => self.h3,
+            HeadingLevel::H4 => self.h4,
+            HeadingLevel::H5 => self.h5,
+            HeadingLevel::H6 => self.h6,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct HeadingScaleContent {
+    /// Default: 2.0
+    pub h1: Option<f32>,
+    /// Default: 1.5
+    pub h2: Option<f32>,
+    /// Default: 1.25
+    pub h3: Option<f32>,
+    /// Default: 1.0
+    pub h4: Option<f32>,
+    /// Default: 0.875
+    pub h5: Option<f32>,
+    /// Default: 0.85
+    pub h6: Option<f32>,
+}
+
+/// Overrides of the preview's appearance, such as to match a documentation site.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct PreviewStyle {
+    pub font_family: Option<String>,
+    pub code_font_family: Option<String>,
+    pub heading_color: Option<Hsla>,
+    pub code_block_background: Option<Hsla>,
+    pub code_span_background: Option<Hsla>,
+    pub spacing_scale: f32,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct PreviewStyleContent {
+    /// The font family of the preview's text.
+    ///
+    /// Default: null (the UI font)
+    pub font_family: Option<String>,
+    /// The font family of code blocks.
+    ///
+    /// Default: null (the font of the preview's text)
+    pub code_font_family: Option<String>,
+    /// The color of headings, as a hex color such as `"#0969da"`.
+    ///
+    /// Default: null (the theme's text color)
+    pub heading_color: Option<String>,
+    /// The background color of code blocks.
+    ///
+    /// Default: null (derived from the theme)
+    pub code_block_background: Option<String>,
+    /// The background color of inline code.
+    ///
+    /// Default: null (derived from the theme)
+    pub code_span_background: Option<String>,
+    /// How much space to leave between blocks, as a multiple of the usual spacing.
+    ///
+    /// Default: 1.0
+    pub spacing_scale: Option<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MarkdownPreviewSettings {
+    pub font_size: Option<Pixels>,
+    pub heading_scale: HeadingScale,
+    pub minimum_contrast: f32,
+    pub style: PreviewStyle,
+    pub placement: PreviewPlacement,
+    pub content_width: PreviewContentWidth,
+    pub code_block_line_numbers: bool,
+    pub smart_typography: bool,
+    pub hard_line_breaks: bool,
+    pub theme: Option<String>,
+    pub code_block_theme: Option<String>,
+    pub refresh_mode: PreviewRefreshMode,
+    pub debounce_ms: u64,
+    pub follow_cursor: bool,
+    pub load_images: bool,
+    pub image_directory: String,
+    pub graphviz_diagrams: bool,
+    pub performance_overlay: bool,
+}
+
+impl MarkdownPreviewSettings {
+    /// The theme the preview is rendered with, falling back to the active theme.
+    ///
+    /// This is looked up on every frame, so an unknown theme is ignored here,
+    /// and reported by [`Self::log_unknown_themes`] when the settings change.
+    pub fn preview_theme(&self, cx: &AppContext) -> Arc<Theme> {
+        self.theme
+            .as_deref()
+            .and_then(|name| ThemeRegistry::global(cx).get(name).ok())
+            .unwrap_or_else(|| cx.theme().clone())
+    }
+
+    /// The syntax theme that code is highlighted with, falling back to
+    /// the syntax theme of [`Self::preview_theme`].
+    pub fn code_block_syntax_theme(&self, cx: &AppContext) -> Arc<SyntaxTheme> {
+        self.code_block_theme
+            .as_deref()
+            .and_then(|name| ThemeRegistry::global(cx).get(name).ok())
+            .unwrap_or_else(|| self.preview_theme(cx))
+            .syntax()
+            .clone()
+    }
+
+    /// Logs the themes that the settings name but that don't exist.
+    pub(crate) fn log_unknown_themes(&self, cx: &AppContext) {
+        let registry = ThemeRegistry::global(cx);
+        for name in [&self.theme, &self.code_block_theme].into_iter().flatten() {
+            registry.get(name).log_err();
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct MarkdownPreviewSettingsContent {
+    /// Sets the font size of the rendered Markdown.
+    ///
+    /// If this option is not included,
+    /// the preview will default to matching the UI font size.
+    pub font_size: Option<f32>,
+    /// How large each level of heading is, as a multiple of the font size of body text.
+    ///
+    /// Default: { "h1": 2.0, "h2": 1.5, "h3": 1.25, "h4": 1.0, "h5": 0.875, "h6": 0.85 }
+    pub heading_scale: Option<HeadingScaleContent>,
+    /// The lowest contrast ratio that muted text may have against the background,
+    /// and that text may have against the background of code. Colors from the theme
+    /// with less contrast are lightened or darkened until they reach it.
+    /// 4.5 is the ratio that WCAG AA requires for body text, and 1 disables the adjustment.
+    ///
+    /// Default: 4.5
+    pub minimum_contrast: Option<f32>,
+    /// Overrides of the preview's fonts, colors and spacing. Like other settings,
+    /// these can be set for a single project in its `.zed/settings.json`.
+    pub style: Option<PreviewStyleContent>,
+    /// Where to open the preview when no placement has been chosen yet.
+    /// Afterwards, the last placement that was used is remembered.
+    ///
+    /// Default: right
+    pub placement: Option<PreviewPlacement>,
+    /// How wide the rendered content may grow before it is centered.
+    ///
+    /// Default: max_width: 800
+    pub content_width: Option<PreviewContentWidth>,
+    /// Whether to show line numbers alongside rendered code blocks.
+    ///
+    /// Default: false
+    pub code_block_line_numbers: Option<bool>,
+    /// Whether to render straight quotes, `--`, `---` and `...` as curly quotes,
+    /// en dashes, em dashes and ellipses.
+    ///
+    /// Default: false
+    pub smart_typography: Option<bool>,
+    /// Whether a single newline within a paragraph starts a new line, as on GitHub,
+    /// instead of joining the lines into one as CommonMark specifies.
+    ///
+    /// Default: false
+    pub hard_line_breaks: Option<bool>,
+    /// The name of the theme to render the preview with, independently of the editor theme.
+    ///
+    /// Default: null (use the active theme)
+    pub theme: Option<String>,
+    /// The name of the theme whose syntax colors are used to highlight code.
+    /// A single code block can use another theme with a `theme` attribute
+    /// on its fence, as in ```` ```rust theme="One Light" ````.
+    ///
+    /// Default: null (use the preview theme)
+    pub code_block_theme: Option<String>,
+    /// Whether the preview re-renders automatically as the document is edited,
+    /// or only when refreshed with `markdown::Refresh`.
+    ///
+    /// Default: automatic
+    pub refresh_mode: Option<PreviewRefreshMode>,
+    /// How long to wait after the last edit before re-rendering the preview,
+    /// in milliseconds.
+    ///
+    /// Default: 100
+    pub debounce_ms: Option<u64>,
+    /// Whether to scroll the preview to the block under the cursor
+    /// as the cursor moves through the document.
+    ///
+    /// Default: true
+    pub follow_cursor: Option<bool>,
+    /// Whether to load and display images. When disabled,
+    /// images are shown as their alt text.
+    ///
+    /// Default: true
+    pub load_images: Option<bool>,
+    /// The directory, relative to the document, that images pasted into
+    /// the preview with `markdown::PasteImage` are copied into.
+    ///
+    /// Default: assets
+    pub image_directory: Option<String>,
+    /// Whether to render ```` ```dot ```` and ```` ```graphviz ```` code blocks as
+    /// diagrams. This requires Graphviz's `dot` command to be installed.
+    ///
+    /// Default: false
+    pub graphviz_diagrams: Option<bool>,
+    /// Whether to show how long the document took to parse, and how long
+    /// the blocks of each frame took to build, over the preview.
+    ///
+    /// Default: false
+    pub performance_overlay: Option<bool>,
+}
+
+impl Settings for MarkdownPreviewSettings {
+    const KEY: Option<&'static str> = Some("markdown_preview");
+
+    type FileContent = MarkdownPreviewSettingsContent;
+
+    fn load(
+        default_value: &Self::FileContent,
+        user_values: &[&Self::FileContent],
+        _: &mut gpui::AppContext,
+    ) -> anyhow::Result<Self> {
+        Self::load_via_json_merge(default_value, user_values)
+    }
+}
@@ -0,0 +1,81 @@
+use gpui::{EventEmitter, ParentElement, Render, Subscription, View, ViewContext};
+use ui::prelude::*;
+use ui::{IconButton, IconName, Tooltip};
+use workspace::{item::ItemHandle, ToolbarItemEvent, ToolbarItemLocation, ToolbarItemView};
+
+use crate::markdown_preview_view::{MarkdownPreviewView, PreviewMode};
+use crate::{ToggleSource, ToggleSplit};
+
+/// Switches the active preview between its rendered output, its source, or both.
+pub struct MarkdownPreviewToolbar {
+    preview: Option<View<MarkdownPreviewView>>,
+    _preview_subscription: Option<Subscription>,
+}
+
+impl Render for MarkdownPreviewToolbar {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let Some(preview) = self.preview.clone() else {
+            return div();
+        };
+        let mode = preview.read(cx).mode();
+
+        h_flex()
+            .gap_1()
+            .child(
+                IconButton::new("markdown-preview-toggle-source", IconName::Hash)
+                    .selected(mode == PreviewMode::Source)
+                    .tooltip(|cx| Tooltip::for_action("Toggle Source", &ToggleSource, cx))
+                    .on_click({
+                        let preview = preview.clone();
+                        move |_, cx| {
+                            preview.update(cx, |preview, cx| {
+                                preview.toggle_mode(PreviewMode::Source, cx)
+                            })
+                        }
+                    }),
+            )
+            .child(
+                IconButton::new("markdown-preview-toggle-split", IconName::Split)
+                    .selected(mode == PreviewMode::Split)
+                    .tooltip(|cx| {
+                        Tooltip::for_action("Toggle Source and Preview", &ToggleSplit, cx)
+                    })
+                    .on_click(move |_, cx| {
+                        preview.update(cx, |preview, cx| {
+                            preview.toggle_mode(PreviewMode::Split, cx)
+                        })
+                    }),
+            )
+    }
+}
+
+impl EventEmitter<ToolbarItemEvent> for MarkdownPreviewToolbar {}
+
+impl ToolbarItemView for MarkdownPreviewToolbar {
+    fn set_active_pane_item(
+        &mut self,
+        active_pane_item: Option<&dyn ItemHandle>,
+        cx: &mut ViewContext<Self>,
+    ) -> ToolbarItemLocation {
+        self.preview = active_pane_item.and_then(|item| item.downcast::<MarkdownPreviewView>());
+        self._preview_subscription = self
+            .preview
+            .as_ref()
+            .map(|preview| cx.observe(preview, |_, _, cx| cx.notify()));
+
+        if self.preview.is_some() {
+            ToolbarItemLocation::PrimaryRight
+        } else {
+            ToolbarItemLocation::Hidden
+        }
+    }
+}
+
+impl MarkdownPreviewToolbar {
+    pub fn new() -> Self {
+        MarkdownPreviewToolbar {
+            preview: None,
+            _preview_subscription: None,
+        }
+    }
+}
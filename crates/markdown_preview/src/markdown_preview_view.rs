@@ -1,132 +1,1587 @@
-use std::{ops::Range, path::PathBuf};
+use std::{
+    collections::HashMap,
+    mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context as _};
 
-use editor::{Editor, EditorEvent};
+use db::kvp::KEY_VALUE_STORE;
+use editor::{Editor, EditorEvent, MultiBufferSnapshot, ToOffset};
 use gpui::{
-    list, AnyElement, AppContext, EventEmitter, FocusHandle, FocusableView, InteractiveElement,
-    IntoElement, ListState, ParentElement, Render, Styled, View, ViewContext, WeakView,
+    list, AnyElement, AppContext, ClickEvent, EventEmitter, FocusHandle, FocusableView, Global,
+    InteractiveElement, IntoElement, ListOffset, ListState, Model, MouseButton, ParentElement,
+    Pixels, Render, Styled, Subscription, Task, View, ViewContext, WeakView,
+};
+use language::{Buffer, LanguageRegistry, Point, Rope};
+use project::{
+    CopyOptions, File, Fs, PathChange, Project, ProjectPath, Snapshot, UpdatedEntriesSet,
+    WorktreeId,
 };
-use ui::prelude::*;
-use workspace::item::Item;
-use workspace::Workspace;
+use settings::SettingsStore;
+use theme::Theme;
+use ui::{prelude::*, Indicator};
+use util::ResultExt;
+use workspace::item::{BreadcrumbText, Item, ItemEvent};
+use workspace::{
+    ItemId, Pane, SaveIntent, SplitDirection, ToolbarItemLocation, Workspace, WorkspaceId,
+};
+use zed_actions::PreviewFile;
 
 use crate::{
-    markdown_elements::ParsedMarkdown,
-    markdown_parser::parse_markdown,
+    markdown_elements::{
+        MarkdownImageSource, ParsedMarkdown, ParsedMarkdownElement, ParsedMarkdownHeading,
+    },
+    markdown_images::{is_image_path, loaded_images, rendered_diagrams, ImageLoader},
+    markdown_parser::{can_parse_in_isolation, has_document_references, parse_markdown},
+    markdown_preview_settings::{
+        MarkdownPreviewSettings, PreviewContentWidth, PreviewPlacement, PreviewRefreshMode,
+    },
     markdown_renderer::{render_markdown_block, RenderContext},
-    OpenPreview,
+    persistence::MARKDOWN_PREVIEW_DB,
+    NextHeading, OpenPreview, OpenPreviewBelow, OpenPreviewForBuffer, OpenPreviewInCurrentPane,
+    OpenPreviewInNewWindow, OpenPreviewToTheRight, PasteImage, PreviousHeading, Refresh, ResetZoom,
+    ScrollPageDown, ScrollPageUp, ScrollToBottom, ScrollToTop, TogglePreview, TogglePreviewPin,
+    ToggleSource, ToggleSplit, ZoomIn, ZoomOut,
 };
 
+const LAST_PREVIEW_PLACEMENT_KEY: &'static str = "MarkdownPreviewPlacement";
+
+/// The placement the user last explicitly chose to open a preview with, which
+/// takes precedence over the configured placement for `OpenPreview`, until
+/// the configured placement changes.
+struct LastPreviewPlacement(PreviewPlacement);
+
+impl Global for LastPreviewPlacement {}
+
+impl LastPreviewPlacement {
+    fn get(cx: &AppContext) -> PreviewPlacement {
+        cx.try_global::<Self>()
+            .map(|placement| placement.0)
+            .unwrap_or_else(|| MarkdownPreviewSettings::get_global(cx).placement)
+    }
+
+    fn set(placement: PreviewPlacement, cx: &mut AppContext) {
+        cx.set_global(Self(placement));
+        cx.background_executor()
+            .spawn(
+                async move {
+                    KEY_VALUE_STORE
+                        .write_kvp(
+                            LAST_PREVIEW_PLACEMENT_KEY.into(),
+                            serde_json::to_string(&placement)?,
+                        )
+                        .await?;
+                    anyhow::Ok(())
+                }
+                .log_err(),
+            )
+            .detach();
+    }
+
+    fn clear(cx: &mut AppContext) {
+        if !cx.has_global::<Self>() {
+            return;
+        }
+        cx.remove_global::<Self>();
+        cx.background_executor()
+            .spawn(KEY_VALUE_STORE.delete_kvp(LAST_PREVIEW_PLACEMENT_KEY.into()))
+            .detach_and_log_err(cx);
+    }
+}
+
+/// Restores the placement remembered from a previous session, and forgets
+/// it whenever the configured placement changes, so that changing the
+/// setting takes effect.
+pub(crate) fn load_last_placement(cx: &mut AppContext) {
+    let mut configured_placement = MarkdownPreviewSettings::get_global(cx).placement;
+    cx.observe_global::<SettingsStore>(move |cx| {
+        let placement = MarkdownPreviewSettings::get_global(cx).placement;
+        if placement != configured_placement {
+            configured_placement = placement;
+            LastPreviewPlacement::clear(cx);
+        }
+    })
+    .detach();
+
+    cx.spawn(|mut cx| async move {
+        let placement = cx
+            .background_executor()
+            .spawn(async move { KEY_VALUE_STORE.read_kvp(LAST_PREVIEW_PLACEMENT_KEY) })
+            .await
+            .log_err()
+            .flatten()
+            .and_then(|placement| serde_json::from_str::<PreviewPlacement>(&placement).log_err());
+
+        if let Some(placement) = placement {
+            cx.update(|cx| cx.set_global(LastPreviewPlacement(placement)))
+                .log_err();
+        }
+    })
+    .detach();
+}
+
+/// What the preview shows of its document.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PreviewMode {
+    /// The rendered Markdown.
+    #[default]
+    Rendered,
+    /// The Markdown source, highlighted and read-only.
+    Source,
+    /// The source and the rendered Markdown side by side.
+    Split,
+}
+
 pub struct MarkdownPreviewView {
     workspace: WeakView<Workspace>,
+    active_editor: Option<EditorState>,
+    mode: PreviewMode,
+    /// A read-only editor over the source of the active editor,
+    /// shown when the mode includes the source.
+    source_editor: Option<View<Editor>>,
+    /// Whether the preview stays on its current document
+    /// instead of following the active Markdown editor.
+    pinned: bool,
     focus_handle: FocusHandle,
     contents: ParsedMarkdown,
     selected_block: usize,
     list_state: ListState,
+    /// How far the user has zoomed the preview in or out
+    /// relative to the configured font size.
+    font_size_adjustment: Pixels,
+    /// Whether the document has been edited since it was last rendered,
+    /// when the preview is only refreshed manually.
+    needs_refresh: bool,
+    parsing_markdown_task: Option<Task<anyhow::Result<()>>>,
+    /// The snapshot of the source that the contents were parsed from,
+    /// through which positions in the contents are carried across later edits.
+    contents_source: Option<MultiBufferSnapshot>,
+    /// Whether blocks of the contents depend on others, such as through reference
+    /// definitions, so that the document has to be re-parsed as a whole after edits.
+    contents_have_references: bool,
+    /// Whether something the contents depend on besides their source, such as the
+    /// settings or images, has changed, so the next parse has to cover the whole document.
+    needs_full_parse: bool,
+    /// The Markdown files in the previewed document's worktree, which are listed
+    /// once and kept until Markdown files are added to or removed from it.
+    markdown_files: Option<(WorktreeId, Arc<Vec<PathBuf>>)>,
+    /// How long the contents took to parse, including loading their images
+    /// and highlighting their code.
+    parse_time: Duration,
+    /// How many blocks have been built for the current frame, and how long that took,
+    /// to be shown in the performance overlay of the next frame.
+    frame_block_count: usize,
+    frame_block_time: Duration,
+    /// The database id of the workspace this preview has been added to.
+    workspace_id: Option<WorkspaceId>,
+    /// The first visible block of each document this preview has shown,
+    /// so that returning to a document restores where it was scrolled to.
+    scroll_positions: HashMap<PathBuf, usize>,
+    /// The block to scroll to once the current document has been parsed.
+    pending_scroll_top: Option<usize>,
+    /// The first block that is visible in the preview.
+    top_visible_block: usize,
+    /// The source that is selected in the editor, whose blocks are highlighted.
+    selected_source_range: Option<Range<usize>>,
+    /// The anchor and head of the blocks selected by clicking in the preview,
+    /// whose source is highlighted in the editor.
+    block_selection: Option<(usize, usize)>,
+    _workspace_subscription: Option<Subscription>,
+    _settings_subscription: Subscription,
+    _project_subscription: Option<Subscription>,
+}
+
+struct EditorState {
+    editor: View<Editor>,
+    _subscription: Subscription,
+}
+
+/// The blocks of the contents that an edit could have changed, which are re-parsed
+/// on their own and spliced into the contents, rather than re-parsing the whole document.
+struct BlockReparse {
+    /// The snapshot of the source that the contents were parsed from.
+    old_snapshot: MultiBufferSnapshot,
+    /// The indices of the blocks to replace.
+    blocks: Range<usize>,
+    /// The source of those blocks before the edit.
+    old_source: Range<usize>,
+    /// The source of those blocks after the edit.
+    new_source: Range<usize>,
+    /// How much the edit changed the length of the source.
+    delta: isize,
+    /// Where the unedited blocks that are re-parsed on either side of the edit
+    /// are in the source after the edit.
+    leading_block: Option<Range<usize>>,
+    trailing_block: Option<Range<usize>>,
+}
+
+impl BlockReparse {
+    /// Finds the blocks, parsed from `old_snapshot`, that the edits made to
+    /// the buffer since then could have changed.
+    fn new(
+        blocks: &[ParsedMarkdownElement],
+        old_snapshot: &MultiBufferSnapshot,
+        snapshot: &MultiBufferSnapshot,
+    ) -> Option<Self> {
+        let (_, old_buffer_id, old_buffer) = old_snapshot.as_singleton()?;
+        let (_, buffer_id, buffer) = snapshot.as_singleton()?;
+        if old_buffer_id != buffer_id {
+            return None;
+        }
+
+        let mut edits = buffer.edits_since::<usize>(old_buffer.version());
+        let first_edit = edits.next()?;
+        let mut edited = first_edit.old.clone();
+        let mut delta = first_edit.new.end as isize - first_edit.old.end as isize;
+        for edit in edits {
+            edited.end = edit.old.end;
+            delta = edit.new.end as isize - edit.old.end as isize;
+        }
+
+        // One unedited block on either side is re-parsed along with the edited ones,
+        // in case the edit joined them together or split one of them.
+        let first_edited = blocks.partition_point(|block| block.source_range().end < edited.start);
+        let edited_end = blocks.partition_point(|block| block.source_range().start <= edited.end);
+        let start_ix = first_edited.saturating_sub(1);
+        let end_ix = (edited_end + 1).min(blocks.len());
+        let leading_block = (start_ix < first_edited).then(|| blocks[start_ix].source_range());
+        let trailing_block = (end_ix > edited_end).then(|| blocks[end_ix - 1].source_range());
+
+        // Without a block on one side, the re-parse runs to that end of the document.
+        let old_start = leading_block.as_ref().map_or(0, |range| {
+            let row = old_snapshot.offset_to_point(range.start).row;
+            old_snapshot.point_to_offset(Point::new(row, 0))
+        });
+        let old_end = trailing_block
+            .as_ref()
+            .map_or(old_snapshot.len(), |range| range.end);
+
+        Some(Self {
+            old_snapshot: old_snapshot.clone(),
+            blocks: start_ix..end_ix,
+            old_source: old_start..old_end,
+            new_source: old_start..old_end.saturating_add_signed(delta),
+            delta,
+            leading_block,
+            trailing_block: trailing_block.map(|range| {
+                range.start.saturating_add_signed(delta)..range.end.saturating_add_signed(delta)
+            }),
+        })
+    }
+
+    /// Re-parses the blocks with `parse`, unless they might parse differently
+    /// without the rest of the document, which then has to be parsed as a whole.
+    fn parse(
+        self,
+        snapshot: &MultiBufferSnapshot,
+        parse: impl Fn(&str) -> ParsedMarkdown,
+    ) -> Option<ParsedContents> {
+        let old_text = self
+            .old_snapshot
+            .text_for_range(self.old_source.clone())
+            .collect::<String>();
+        let new_text = snapshot
+            .text_for_range(self.new_source.clone())
+            .collect::<String>();
+        if !can_parse_in_isolation(&old_text) || !can_parse_in_isolation(&new_text) {
+            return None;
+        }
+
+        let mut blocks = parse(&new_text);
+        for block in &mut blocks.children {
+            block.offset_source_ranges(self.new_source.start as isize);
+        }
+
+        // The edit could have changed blocks beyond the unedited ones
+        // if it changed where those start or end.
+        let first_block = blocks.children.first().map(|block| block.source_range());
+        let last_block = blocks.children.last().map(|block| block.source_range());
+        if (self.leading_block.is_some() && first_block != self.leading_block)
+            || (self.trailing_block.is_some() && last_block != self.trailing_block)
+        {
+            return None;
+        }
+
+        Some(ParsedContents::Blocks {
+            replaced: self.blocks,
+            blocks,
+            delta: self.delta,
+        })
+    }
+}
+
+/// The Markdown parsed from the previewed document after it changed.
+enum ParsedContents {
+    /// The whole document, and whether its blocks depend on each other.
+    Document {
+        contents: ParsedMarkdown,
+        has_references: bool,
+    },
+    /// The blocks around an edit, which replace the blocks of the contents at `replaced`.
+    Blocks {
+        replaced: Range<usize>,
+        blocks: ParsedMarkdown,
+        delta: isize,
+    },
+}
+
+impl ParsedContents {
+    fn markdown_mut(&mut self) -> &mut ParsedMarkdown {
+        match self {
+            Self::Document { contents, .. } => contents,
+            Self::Blocks { blocks, .. } => blocks,
+        }
+    }
 }
 
 impl MarkdownPreviewView {
-    pub fn register(workspace: &mut Workspace, _cx: &mut ViewContext<Workspace>) {
+    pub fn register(workspace: &mut Workspace, cx: &mut ViewContext<Workspace>) {
         workspace.register_action(move |workspace, _: &OpenPreview, cx| {
-            if workspace.has_active_modal(cx) {
-                cx.propagate();
-                return;
+            let placement = LastPreviewPlacement::get(cx);
+            Self::deploy_preview(workspace, placement, cx);
+        });
+        workspace.register_action(move |workspace, _: &OpenPreviewForBuffer, cx| {
+            Self::deploy_preview_for_any_buffer(workspace, cx);
+        });
+        workspace.register_action(move |workspace, action: &PreviewFile, cx| {
+            Self::preview_file(workspace, &action.path, cx);
+        });
+        workspace.register_action(move |workspace, _: &TogglePreview, cx| {
+            Self::toggle_preview(workspace, cx);
+        });
+        workspace.register_action(move |workspace, _: &OpenPreviewToTheRight, cx| {
+            LastPreviewPlacement::set(PreviewPlacement::Right, cx);
+            Self::deploy_preview(workspace, PreviewPlacement::Right, cx);
+        });
+        workspace.register_action(move |workspace, _: &OpenPreviewBelow, cx| {
+            LastPreviewPlacement::set(PreviewPlacement::Below, cx);
+            Self::deploy_preview(workspace, PreviewPlacement::Below, cx);
+        });
+        workspace.register_action(move |workspace, _: &OpenPreviewInCurrentPane, cx| {
+            LastPreviewPlacement::set(PreviewPlacement::CurrentPane, cx);
+            Self::deploy_preview(workspace, PreviewPlacement::CurrentPane, cx);
+        });
+        workspace.register_action(move |workspace, _: &OpenPreviewInNewWindow, cx| {
+            LastPreviewPlacement::set(PreviewPlacement::NewWindow, cx);
+            Self::deploy_preview(workspace, PreviewPlacement::NewWindow, cx);
+        });
+
+        let workspace_handle = cx.view().clone();
+        for pane in workspace.panes() {
+            Self::add_tab_context_menu_entry(workspace_handle.downgrade(), pane, cx);
+        }
+        cx.subscribe(&workspace_handle, |workspace, _, event, cx| {
+            if let workspace::Event::PaneAdded(pane) = event {
+                Self::add_tab_context_menu_entry(workspace.weak_handle(), pane, cx);
             }
+        })
+        .detach();
+    }
 
-            if let Some(editor) = workspace.active_item_as::<Editor>(cx) {
-                let workspace_handle = workspace.weak_handle();
-                let view: View<MarkdownPreviewView> =
-                    MarkdownPreviewView::new(editor, workspace_handle, cx);
-                workspace.split_item(workspace::SplitDirection::Right, Box::new(view.clone()), cx);
-                cx.notify();
+    /// Adds an entry for previewing editors to the pane's tab context menu,
+    /// so that any tab can be previewed as Markdown without activating it first.
+    fn add_tab_context_menu_entry(
+        workspace: WeakView<Workspace>,
+        pane: &View<Pane>,
+        cx: &mut WindowContext,
+    ) {
+        pane.update(cx, |pane, cx| {
+            pane.add_tab_context_menu_extension(cx, move |menu, item, cx| {
+                let Some(editor) = item.act_as::<Editor>(cx) else {
+                    return menu;
+                };
+                let label = if Self::is_markdown_file(&editor, cx) {
+                    "Open Markdown Preview"
+                } else {
+                    "Preview as Markdown"
+                };
+
+                let workspace = workspace.clone();
+                menu.separator().entry(label, None, move |cx| {
+                    let editor = editor.clone();
+                    workspace
+                        .update(cx, |workspace, cx| {
+                            let placement = LastPreviewPlacement::get(cx);
+                            Self::open_preview_for_editor(workspace, editor, placement, cx);
+                        })
+                        .log_err();
+                })
+            });
+        });
+    }
+
+    /// Closes the preview for the active Markdown editor if one is showing,
+    /// and opens one otherwise.
+    fn toggle_preview(workspace: &mut Workspace, cx: &mut ViewContext<Workspace>) {
+        if workspace.has_active_modal(cx) {
+            cx.propagate();
+            return;
+        }
+
+        let existing_preview = if let Some(preview) = workspace.active_item_as::<Self>(cx) {
+            Some(preview)
+        } else if let Some(editor) = workspace.active_item_as::<Editor>(cx) {
+            workspace
+                .items_of_type::<Self>(cx)
+                .find(|preview| preview.read(cx).is_previewing(&editor))
+        } else {
+            None
+        };
+
+        if let Some(preview) = existing_preview {
+            if let Some(pane) = workspace.pane_for(&preview) {
+                pane.update(cx, |pane, cx| {
+                    pane.close_item_by_id(preview.entity_id(), SaveIntent::Skip, cx)
+                        .detach_and_log_err(cx);
+                });
             }
+            return;
+        }
+
+        let placement = LastPreviewPlacement::get(cx);
+        Self::deploy_preview(workspace, placement, cx);
+    }
+
+    fn deploy_preview(
+        workspace: &mut Workspace,
+        placement: PreviewPlacement,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        if workspace.has_active_modal(cx) {
+            cx.propagate();
+            return;
+        }
+
+        // Prefer the active editor, but fall back to a Markdown editor elsewhere
+        // in the active pane when the active item can't be previewed.
+        let editor = workspace
+            .active_item_as::<Editor>(cx)
+            .filter(|editor| Self::is_markdown_file(editor, cx))
+            .or_else(|| {
+                workspace
+                    .active_pane()
+                    .read(cx)
+                    .items_of_type::<Editor>()
+                    .find(|editor| Self::is_markdown_file(editor, cx))
+            });
+        let Some(editor) = editor else {
+            return;
+        };
+
+        Self::open_preview_for_editor(workspace, editor, placement, cx);
+    }
+
+    /// Previews the active editor as Markdown, whatever its language,
+    /// so untitled buffers and files with other extensions can be rendered.
+    fn deploy_preview_for_any_buffer(workspace: &mut Workspace, cx: &mut ViewContext<Workspace>) {
+        if workspace.has_active_modal(cx) {
+            cx.propagate();
+            return;
+        }
+
+        let Some(editor) = workspace.active_item_as::<Editor>(cx) else {
+            return;
+        };
+        let placement = LastPreviewPlacement::get(cx);
+        Self::open_preview_for_editor(workspace, editor, placement, cx);
+    }
+
+    /// Previews a file straight from disk, rendering it through an editor
+    /// that isn't shown in any pane. The preview is pinned, since there is
+    /// no editor tab it could be following.
+    fn preview_file(workspace: &mut Workspace, path: &Path, cx: &mut ViewContext<Workspace>) {
+        let project = workspace.project().clone();
+        let open_buffer = Self::open_buffer(&project, path, cx);
+        cx.spawn(|workspace, mut cx| async move {
+            let buffer = open_buffer.await?;
+            workspace.update(&mut cx, |workspace, cx| {
+                let editor = cx.new_view(|cx| Editor::for_buffer(buffer, Some(project), cx));
+                let preview = MarkdownPreviewView::new(editor, workspace.weak_handle(), cx);
+                preview.update(cx, |preview, _| preview.pinned = true);
+                workspace.add_item(Box::new(preview), cx);
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    fn open_buffer(
+        project: &Model<Project>,
+        path: &Path,
+        cx: &mut WindowContext,
+    ) -> Task<anyhow::Result<Model<Buffer>>> {
+        let open_path = project.update(cx, |project, cx| {
+            let (worktree, relative_path) = project
+                .find_local_worktree(path, cx)
+                .with_context(|| format!("No worktree for path: {path:?}"))?;
+            let project_path = ProjectPath {
+                worktree_id: worktree.read(cx).id(),
+                path: relative_path.into(),
+            };
+            anyhow::Ok(project.open_path(project_path, cx))
         });
+
+        cx.spawn(|_| async move {
+            let (_, buffer) = open_path?.await?;
+            buffer
+                .downcast::<Buffer>()
+                .map_err(|_| anyhow!("Project item at path was not a buffer"))
+        })
+    }
+
+    fn open_preview_for_editor(
+        workspace: &mut Workspace,
+        editor: View<Editor>,
+        placement: PreviewPlacement,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let existing_preview = workspace
+            .items_of_type::<Self>(cx)
+            .find(|preview| preview.read(cx).is_previewing(&editor));
+        if let Some(preview) = existing_preview {
+            workspace.activate_item(&preview, cx);
+            return;
+        }
+
+        // Each additional preview stays on the document it was opened for,
+        // rather than every preview following the active editor.
+        let pinned = workspace.items_of_type::<Self>(cx).next().is_some();
+        match placement {
+            PreviewPlacement::Right | PreviewPlacement::Below => {
+                let direction = if placement == PreviewPlacement::Right {
+                    SplitDirection::Right
+                } else {
+                    SplitDirection::Down
+                };
+                let view = MarkdownPreviewView::new(editor, workspace.weak_handle(), cx);
+                view.update(cx, |view, _| view.pinned = pinned);
+                workspace.split_item(direction, Box::new(view), cx);
+            }
+            PreviewPlacement::CurrentPane => {
+                let view = MarkdownPreviewView::new(editor, workspace.weak_handle(), cx);
+                view.update(cx, |view, _| view.pinned = pinned);
+                workspace.add_item(Box::new(view), cx);
+            }
+            PreviewPlacement::NewWindow => {
+                let app_state = workspace.app_state().clone();
+                let project = workspace.project().clone();
+                let options = (app_state.build_window_options)(None, None, cx);
+                // The new window shows the same project as this one, so it's kept out
+                // of the database, where it would replace this window's saved workspace.
+                let window = cx.open_window(options, |cx| {
+                    cx.new_view(|cx| {
+                        let mut workspace = Workspace::new(0, project, app_state, cx);
+                        workspace.disable_serialization();
+                        workspace
+                    })
+                });
+                window
+                    .update(cx, |workspace, cx| {
+                        let view = MarkdownPreviewView::new(editor, workspace.weak_handle(), cx);
+                        view.update(cx, |view, _| view.pinned = pinned);
+                        workspace.add_item(Box::new(view), cx);
+                    })
+                    .log_err();
+            }
+        }
+
+        cx.notify();
     }
 
     pub fn new(
         active_editor: View<Editor>,
         workspace: WeakView<Workspace>,
-        cx: &mut ViewContext<Workspace>,
+        cx: &mut WindowContext,
     ) -> View<Self> {
         cx.new_view(|cx: &mut ViewContext<Self>| {
             let view = cx.view().downgrade();
-            let editor = active_editor.read(cx);
-
-            let file_location = MarkdownPreviewView::get_folder_for_active_editor(editor, cx);
-            let contents = editor.buffer().read(cx).snapshot(cx).text();
-            let contents = parse_markdown(&contents, file_location);
-
-            cx.subscribe(&active_editor, |this, editor, event: &EditorEvent, cx| {
-                match event {
-                    EditorEvent::Edited => {
-                        let editor = editor.read(cx);
-                        let contents = editor.buffer().read(cx).snapshot(cx).text();
-                        let file_location =
-                            MarkdownPreviewView::get_folder_for_active_editor(editor, cx);
-                        this.contents = parse_markdown(&contents, file_location);
-                        this.list_state.reset(this.contents.children.len());
-                        cx.notify();
+            let scroll_view = view.clone();
 
-                        // TODO: This does not work as expected.
-                        // The scroll request appears to be dropped
-                        // after `.reset` is called.
-                        this.list_state.scroll_to_reveal_item(this.selected_block);
-                        cx.notify();
-                    }
-                    EditorEvent::SelectionsChanged { .. } => {
-                        let editor = editor.read(cx);
-                        let selection_range = editor.selections.last::<usize>(cx).range();
-                        this.selected_block = this.get_block_index_under_cursor(selection_range);
-                        this.list_state.scroll_to_reveal_item(this.selected_block);
-                        cx.notify();
+            let list_state =
+                ListState::new(0, gpui::ListAlignment::Top, px(1000.), move |ix, cx| {
+                    if let Some(view) = view.upgrade() {
+                        view.update(cx, |view, cx| view.render_block(ix, cx))
+                    } else {
+                        div().into_any()
                     }
-                    _ => {}
-                };
-            })
-            .detach();
+                });
+            list_state.set_scroll_handler(move |event, cx| {
+                scroll_view
+                    .update(cx, |this, cx| {
+                        this.scroll_top_changed(event.visible_range.start, cx)
+                    })
+                    .ok();
+            });
 
-            let list_state = ListState::new(
-                contents.children.len(),
-                gpui::ListAlignment::Top,
-                px(1000.),
-                move |ix, cx| {
-                    if let Some(view) = view.upgrade() {
-                        view.update(cx, |view, cx| {
-                            let mut render_cx =
-                                RenderContext::new(Some(view.workspace.clone()), cx);
-                            let block = view.contents.children.get(ix).unwrap();
-                            let block = render_markdown_block(block, &mut render_cx);
-                            let block = div().child(block).pl_4().pb_3();
-
-                            if ix == view.selected_block {
-                                let indicator = div()
-                                    .h_full()
-                                    .w(px(4.0))
-                                    .bg(cx.theme().colors().border)
-                                    .rounded_sm();
-
-                                return div()
-                                    .relative()
-                                    .child(block)
-                                    .child(indicator.absolute().left_0().top_0())
-                                    .into_any();
+            let workspace_subscription = workspace.upgrade().map(|workspace| {
+                cx.subscribe(
+                    &workspace,
+                    |this, workspace, event: &workspace::Event, cx| {
+                        if let workspace::Event::ActiveItemChanged = event {
+                            if this.pinned {
+                                return;
                             }
 
-                            block.into_any()
-                        })
-                    } else {
-                        div().into_any()
+                            let editor = workspace
+                                .read(cx)
+                                .active_item(cx)
+                                .and_then(|item| item.act_as::<Editor>(cx));
+                            if let Some(editor) = editor {
+                                if Self::is_markdown_file(&editor, cx) {
+                                    this.set_editor(editor, cx);
+                                }
+                            }
+                        }
+                    },
+                )
+            });
+
+            let project_subscription = workspace.upgrade().map(|workspace| {
+                let project = workspace.read(cx).project().clone();
+                cx.subscribe(&project, |this, _, event: &project::Event, cx| {
+                    if let project::Event::WorktreeUpdatedEntries(worktree_id, changes) = event {
+                        this.worktree_entries_changed(*worktree_id, changes);
+                        this.reload_changed_images(changes, cx);
                     }
-                },
-            );
+                })
+            });
 
-            Self {
+            let mut this = Self {
                 selected_block: 0,
                 focus_handle: cx.focus_handle(),
                 workspace,
-                contents,
+                active_editor: None,
+                mode: PreviewMode::default(),
+                source_editor: None,
+                pinned: false,
+                contents: ParsedMarkdown { children: vec![] },
                 list_state,
+                font_size_adjustment: px(0.),
+                needs_refresh: false,
+                parsing_markdown_task: None,
+                workspace_id: None,
+                scroll_positions: HashMap::default(),
+                pending_scroll_top: None,
+                contents_source: None,
+                contents_have_references: false,
+                needs_full_parse: false,
+                markdown_files: None,
+                parse_time: Duration::ZERO,
+                frame_block_count: 0,
+                frame_block_time: Duration::ZERO,
+                top_visible_block: 0,
+                selected_source_range: None,
+                block_selection: None,
+                _workspace_subscription: workspace_subscription,
+                _settings_subscription: cx
+                    .observe_global::<SettingsStore>(|this, cx| this.settings_changed(cx)),
+                _project_subscription: project_subscription,
+            };
+            this.set_editor(active_editor, cx);
+            this
+        })
+    }
+
+    /// Points the preview at the given editor, re-rendering its contents
+    /// and tracking its edits from now on.
+    fn set_editor(&mut self, editor: View<Editor>, cx: &mut ViewContext<Self>) {
+        if let Some(active) = &self.active_editor {
+            if active.editor == editor {
+                return;
+            }
+        }
+
+        let subscription = cx.subscribe(&editor, |this, editor, event: &EditorEvent, cx| {
+            match event {
+                // Unlike `Edited`, this is also emitted for edits made by
+                // collaborators, so their changes render as they arrive.
+                EditorEvent::BufferEdited => {
+                    let settings = Self::settings_for_editor(editor.read(cx), cx);
+                    if settings.refresh_mode == PreviewRefreshMode::Manual {
+                        this.needs_refresh = true;
+                        cx.notify();
+                        return;
+                    }
+
+                    // Wait for a pause in typing, so large documents aren't
+                    // re-parsed on every keystroke.
+                    let debounce = Duration::from_millis(settings.debounce_ms);
+                    this.parse_markdown_from_editor(&editor, debounce, cx);
+                }
+                EditorEvent::SelectionsChanged { local } => {
+                    if *local {
+                        this.clear_block_selection(cx);
+                    }
+
+                    let selection_range = editor.read(cx).selections.last::<usize>(cx).range();
+                    this.selected_source_range =
+                        (!selection_range.is_empty()).then(|| selection_range.clone());
+                    this.selected_block = this.get_block_index_under_cursor(selection_range);
+                    // Selections only change remotely while following a collaborator,
+                    // whose cursor is then followed regardless of the setting.
+                    if !*local || MarkdownPreviewSettings::get_global(cx).follow_cursor {
+                        this.list_state.scroll_to_reveal_item(this.selected_block);
+                    }
+                    cx.notify();
+                }
+                // While following a collaborator, their scroll position is applied to the
+                // editor, and the preview is scrolled to the block at the top of it.
+                EditorEvent::ScrollPositionChanged { local: false, .. } => {
+                    let editor = editor.read(cx);
+                    let buffer = editor.buffer().read(cx).snapshot(cx);
+                    let scroll_top = editor.scroll_manager.anchor().anchor.to_offset(&buffer);
+                    let block = this.get_block_index_at_or_after(scroll_top);
+                    this.scroll_to_block(block, cx);
+                }
+                EditorEvent::DirtyChanged | EditorEvent::Saved | EditorEvent::TitleChanged => {
+                    cx.emit(PreviewEvent::SourceChanged);
+                }
+                _ => {}
+            };
+        });
+
+        self.clear_block_selection(cx);
+        self.selected_block = 0;
+        self.selected_source_range = None;
+        self.contents_source = None;
+        self.source_editor = None;
+        self.pending_scroll_top = Self::path_for_editor(&editor, cx)
+            .and_then(|path| self.scroll_positions.get(&path).copied());
+        self.active_editor = Some(EditorState {
+            editor: editor.clone(),
+            _subscription: subscription,
+        });
+        self.parse_markdown_from_editor(&editor, Duration::ZERO, cx);
+        self.update_source_editor(cx);
+        self.serialize(cx);
+        cx.emit(PreviewEvent::SourceChanged);
+    }
+
+    /// Selects the block at `ix`, or extends the selection to it,
+    /// and highlights the source of the selected blocks in the editor.
+    fn select_block(&mut self, ix: usize, extend: bool, cx: &mut ViewContext<Self>) {
+        let anchor = match self.block_selection {
+            Some((anchor, _)) if extend => anchor,
+            _ => ix,
+        };
+        self.block_selection = Some((anchor, ix));
+
+        let blocks = &self.contents.children;
+        let (Some(first), Some(last)) = (blocks.get(anchor.min(ix)), blocks.get(anchor.max(ix)))
+        else {
+            return;
+        };
+        let source_range = first.source_range().start..last.source_range().end;
+        if let Some(state) = &self.active_editor {
+            state.editor.update(cx, |editor, cx| {
+                let buffer = editor.buffer().read(cx).snapshot(cx);
+                let range =
+                    buffer.anchor_before(source_range.start)..buffer.anchor_after(source_range.end);
+                editor.highlight_background::<Self>(
+                    vec![range],
+                    |colors| colors.editor_document_highlight_read_background,
+                    cx,
+                );
+            });
+        }
+        cx.notify();
+    }
+
+    fn clear_block_selection(&mut self, cx: &mut ViewContext<Self>) {
+        if self.block_selection.take().is_none() {
+            return;
+        }
+        if let Some(state) = &self.active_editor {
+            state.editor.update(cx, |editor, cx| {
+                editor.clear_background_highlights::<Self>(cx);
+            });
+        }
+        cx.notify();
+    }
+
+    fn is_block_highlighted(&self, ix: usize) -> bool {
+        if let Some((anchor, head)) = self.block_selection {
+            if (anchor.min(head)..=anchor.max(head)).contains(&ix) {
+                return true;
+            }
+        }
+
+        let (Some(selected), Some(block)) =
+            (&self.selected_source_range, self.contents.children.get(ix))
+        else {
+            return false;
+        };
+        let block = block.source_range();
+        selected.start < block.end && block.start < selected.end
+    }
+
+    fn path_for_editor(editor: &View<Editor>, cx: &AppContext) -> Option<PathBuf> {
+        let buffer = editor.read(cx).buffer().read(cx).as_singleton()?;
+        let file = buffer.read(cx).file()?.as_local()?;
+        Some(file.abs_path(cx))
+    }
+
+    fn active_path(&self, cx: &AppContext) -> Option<PathBuf> {
+        let editor = &self.active_editor.as_ref()?.editor;
+        Self::path_for_editor(editor, cx)
+    }
+
+    fn scroll_top_changed(&mut self, scroll_top: usize, cx: &mut ViewContext<Self>) {
+        self.set_top_visible_block(scroll_top, cx);
+
+        let Some(path) = self.active_path(cx) else {
+            return;
+        };
+        if self.scroll_positions.insert(path, scroll_top) != Some(scroll_top) {
+            self.serialize(cx);
+        }
+    }
+
+    fn set_top_visible_block(&mut self, block: usize, cx: &mut ViewContext<Self>) {
+        if self.top_visible_block != block {
+            self.top_visible_block = block;
+            cx.emit(PreviewEvent::ScrolledToHeading);
+        }
+    }
+
+    /// Saves the previewed document and its scroll position,
+    /// so the preview can be restored when the workspace is reopened.
+    fn serialize(&self, cx: &mut ViewContext<Self>) {
+        let (Some(workspace_id), Some(path)) = (self.workspace_id, self.active_path(cx)) else {
+            return;
+        };
+        let item_id = cx.view().item_id().as_u64() as ItemId;
+        let scroll_top = self.scroll_positions.get(&path).copied().unwrap_or(0) as u32;
+
+        cx.background_executor()
+            .spawn(async move {
+                MARKDOWN_PREVIEW_DB
+                    .save_preview(item_id, workspace_id, path, scroll_top)
+                    .await
+                    .log_err()
+            })
+            .detach();
+    }
+
+    /// The title of the preview's tab, falling back to a generic
+    /// title before any document has been loaded.
+    fn title(&self, cx: &AppContext) -> SharedString {
+        self.active_editor
+            .as_ref()
+            .map_or("Markdown preview".into(), |active| {
+                let buffer = active.editor.read(cx).buffer().read(cx);
+                format!("Preview: {}", buffer.title(cx)).into()
+            })
+    }
+
+    fn is_source_dirty(&self, cx: &AppContext) -> bool {
+        self.active_editor.as_ref().map_or(false, |active| {
+            active.editor.read(cx).buffer().read(cx).is_dirty(cx)
+        })
+    }
+
+    /// Shows the editor for the previewed document, opening it in the
+    /// active pane when the preview was rendered without a visible editor.
+    fn open_source_editor(
+        workspace: &WeakView<Workspace>,
+        editor: &View<Editor>,
+        cx: &mut WindowContext,
+    ) {
+        workspace
+            .update(cx, |workspace, cx| {
+                if !workspace.activate_item(editor, cx) {
+                    workspace.add_item(Box::new(editor.clone()), cx);
+                }
+            })
+            .log_err();
+    }
+
+    /// Whether this preview is currently rendering the given editor's contents.
+    pub fn is_previewing(&self, editor: &View<Editor>) -> bool {
+        self.active_editor
+            .as_ref()
+            .map_or(false, |active| &active.editor == editor)
+    }
+
+    /// Re-renders the previewed document, picking up any edits made to it
+    /// since it was last rendered.
+    fn refresh(&mut self, _: &Refresh, cx: &mut ViewContext<Self>) {
+        if let Some(editor) = self
+            .active_editor
+            .as_ref()
+            .map(|state| state.editor.clone())
+        {
+            self.parse_markdown_from_editor(&editor, Duration::ZERO, cx);
+        }
+    }
+
+    fn scroll_page_up(&mut self, _: &ScrollPageUp, cx: &mut ViewContext<Self>) {
+        let page_height = self.list_state.viewport_bounds().size.height;
+        self.list_state.scroll_by(-page_height);
+        self.scrolled(cx);
+    }
+
+    fn scroll_page_down(&mut self, _: &ScrollPageDown, cx: &mut ViewContext<Self>) {
+        let page_height = self.list_state.viewport_bounds().size.height;
+        self.list_state.scroll_by(page_height);
+        self.scrolled(cx);
+    }
+
+    fn scroll_to_top(&mut self, _: &ScrollToTop, cx: &mut ViewContext<Self>) {
+        self.scroll_to_block(0, cx);
+    }
+
+    fn scroll_to_bottom(&mut self, _: &ScrollToBottom, cx: &mut ViewContext<Self>) {
+        if let Some(last_block) = self.contents.children.len().checked_sub(1) {
+            self.list_state.scroll_to_reveal_item(last_block);
+            self.scrolled(cx);
+        }
+    }
+
+    fn next_heading(&mut self, _: &NextHeading, cx: &mut ViewContext<Self>) {
+        let next_heading = self
+            .contents
+            .children
+            .iter()
+            .enumerate()
+            .skip(self.top_visible_block + 1)
+            .find(|(_, block)| matches!(block, ParsedMarkdownElement::Heading(_)));
+        if let Some((ix, _)) = next_heading {
+            self.scroll_to_block(ix, cx);
+        }
+    }
+
+    fn previous_heading(&mut self, _: &PreviousHeading, cx: &mut ViewContext<Self>) {
+        let scroll_top = self.list_state.logical_scroll_top();
+        // When the top heading is only partially visible, go back to its start first.
+        let end = if scroll_top.offset_in_item > px(0.) {
+            scroll_top.item_ix + 1
+        } else {
+            scroll_top.item_ix
+        };
+        let previous_heading = self
+            .contents
+            .children
+            .iter()
+            .take(end)
+            .enumerate()
+            .rfind(|(_, block)| matches!(block, ParsedMarkdownElement::Heading(_)));
+        if let Some((ix, _)) = previous_heading {
+            self.scroll_to_block(ix, cx);
+        }
+    }
+
+    fn scroll_to_block(&mut self, block: usize, cx: &mut ViewContext<Self>) {
+        self.list_state.scroll_to(ListOffset {
+            item_ix: block,
+            offset_in_item: px(0.),
+        });
+        self.scrolled(cx);
+    }
+
+    /// The list only reports scrolling done with the mouse,
+    /// so scrolling from key bindings is recorded here.
+    fn scrolled(&mut self, cx: &mut ViewContext<Self>) {
+        let scroll_top = self.list_state.logical_scroll_top().item_ix;
+        self.scroll_top_changed(scroll_top, cx);
+        cx.notify();
+    }
+
+    /// Re-parses the editor's contents after the given delay, replacing any
+    /// parse that is still pending.
+    ///
+    /// When the contents were parsed from an earlier snapshot of the same buffer,
+    /// only the blocks around the edits made since then are re-parsed, and spliced
+    /// into the contents, so typing doesn't re-parse the whole document. Parsing
+    /// happens in the background, so large documents don't block typing in the editor.
+    fn parse_markdown_from_editor(
+        &mut self,
+        editor: &View<Editor>,
+        delay: Duration,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let editor = editor.clone();
+        self.parsing_markdown_task = Some(cx.spawn(|this, mut cx| async move {
+            if !delay.is_zero() {
+                cx.background_executor().timer(delay).await;
             }
+
+            let Some((
+                snapshot,
+                block_reparse,
+                file_location,
+                worktree_id,
+                listed_markdown_files,
+                worktree,
+                smart_typography,
+                hard_line_breaks,
+                image_loader,
+                languages,
+            )) = this.update(&mut cx, |this, cx| {
+                if !this.is_previewing(&editor) {
+                    return None;
+                }
+
+                let editor = editor.read(cx);
+                let snapshot = editor.buffer().read(cx).snapshot(cx);
+                let block_reparse = if this.needs_full_parse {
+                    None
+                } else {
+                    this.block_reparse(&snapshot)
+                };
+                let file_location = MarkdownPreviewView::get_folder_for_active_editor(editor, cx);
+                let worktree = editor
+                    .file_at(0, cx)
+                    .and_then(|file| Some(File::from_dyn(Some(&file))?.worktree.clone()));
+                let worktree_id = worktree.as_ref().map(|worktree| worktree.read(cx).id());
+                let listed_markdown_files = this
+                    .markdown_files
+                    .as_ref()
+                    .filter(|(id, _)| Some(*id) == worktree_id)
+                    .map(|(_, files)| files.clone());
+                // The worktree is only needed to list its Markdown files.
+                let worktree = worktree
+                    .filter(|_| listed_markdown_files.is_none())
+                    .map(|worktree| worktree.read(cx).snapshot());
+                let previous_blocks = match &block_reparse {
+                    Some(block_reparse) => &this.contents.children[block_reparse.blocks.clone()],
+                    None => &this.contents.children[..],
+                };
+                let settings = Self::settings_for_editor(editor, cx);
+                let image_loader = ImageLoader {
+                    scale_factor: cx.scale_factor(),
+                    images: settings.load_images,
+                    graphviz_diagrams: settings.graphviz_diagrams,
+                    previous_diagrams: rendered_diagrams(previous_blocks),
+                    previous_images: loaded_images(previous_blocks),
+                };
+                Some((
+                    snapshot,
+                    block_reparse,
+                    file_location,
+                    worktree_id,
+                    listed_markdown_files,
+                    worktree,
+                    settings.smart_typography,
+                    settings.hard_line_breaks,
+                    image_loader,
+                    this.languages(cx),
+                ))
+            })?
+            else {
+                return Ok(());
+            };
+
+            let parse = cx.background_executor().spawn(async move {
+                let started_at = Instant::now();
+                let markdown_files = listed_markdown_files.unwrap_or_else(|| {
+                    Arc::new(
+                        worktree
+                            .map(|worktree| Self::markdown_files(&worktree))
+                            .unwrap_or_default(),
+                    )
+                });
+                let parse = |text: &str| {
+                    parse_markdown(
+                        text,
+                        file_location.clone(),
+                        &markdown_files,
+                        smart_typography,
+                        hard_line_breaks,
+                    )
+                };
+
+                let mut parsed = block_reparse
+                    .and_then(|block_reparse| block_reparse.parse(&snapshot, &parse))
+                    .unwrap_or_else(|| {
+                        let text = snapshot.text();
+                        ParsedContents::Document {
+                            has_references: has_document_references(&text),
+                            contents: parse(&text),
+                        }
+                    });
+                let markdown = parsed.markdown_mut();
+                image_loader.load(markdown);
+                if let Some(languages) = languages {
+                    Self::highlight_code_blocks(markdown, &languages).await;
+                }
+                (parsed, snapshot, markdown_files, started_at.elapsed())
+            });
+            let (parsed, snapshot, markdown_files, parse_time) = parse.await;
+
+            this.update(&mut cx, |this, cx| {
+                match parsed {
+                    ParsedContents::Document {
+                        contents,
+                        has_references,
+                    } => {
+                        this.set_contents(contents, has_references, &snapshot, cx);
+                        this.needs_full_parse = false;
+                    }
+                    ParsedContents::Blocks {
+                        replaced,
+                        blocks,
+                        delta,
+                    } => {
+                        // Splicing the list keeps the heights of the other blocks,
+                        // and the scroll position, which is moved along with its block.
+                        let count = blocks.children.len();
+                        this.contents
+                            .splice(replaced.clone(), blocks.children, delta);
+                        this.list_state.splice(replaced, count);
+                    }
+                }
+                this.contents_source = Some(snapshot);
+                this.markdown_files = worktree_id.map(|id| (id, markdown_files));
+                this.parse_time = parse_time;
+                this.needs_refresh = false;
+
+                // The headings may have changed, so the breadcrumbs need updating
+                // even if the topmost visible block hasn't.
+                this.top_visible_block = this.list_state.logical_scroll_top().item_ix;
+                cx.emit(PreviewEvent::ScrolledToHeading);
+                cx.notify();
+            })
+        }));
+    }
+
+    /// Re-parses the document when settings change, since they decide how it's parsed,
+    /// and rebuilds every block, whose heights may have changed with the settings.
+    fn settings_changed(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(editor) = self
+            .active_editor
+            .as_ref()
+            .map(|state| state.editor.clone())
+        else {
+            return;
+        };
+        self.needs_full_parse = true;
+        self.parse_markdown_from_editor(&editor, Duration::ZERO, cx);
+    }
+
+    /// The preview settings that apply to the editor's file, including
+    /// those in its project's `.zed/settings.json`.
+    fn settings_for_editor<'a>(editor: &Editor, cx: &'a AppContext) -> &'a MarkdownPreviewSettings {
+        let file = editor.file_at(0usize, cx);
+        MarkdownPreviewSettings::get(
+            file.as_ref()
+                .map(|file| (file.worktree_id(), file.path().as_ref())),
+            cx,
+        )
+    }
+
+    /// Replaces the contents with those of a document that was parsed as a whole.
+    fn set_contents(
+        &mut self,
+        contents: ParsedMarkdown,
+        has_references: bool,
+        snapshot: &MultiBufferSnapshot,
+        cx: &mut ViewContext<Self>,
+    ) {
+        // Resetting the list scrolls it to the top, so the block that was at the top
+        // is found in the new contents, and scrolled back to.
+        let scroll_anchor = self.scroll_anchor(snapshot);
+        self.contents = contents;
+        self.contents_have_references = has_references;
+        self.list_state.reset(self.contents.children.len());
+
+        if let Some(scroll_top) = self.pending_scroll_top.take() {
+            self.list_state.scroll_to(ListOffset {
+                item_ix: scroll_top,
+                offset_in_item: px(0.),
+            });
+        } else if let Some((source_offset, offset_in_item)) = scroll_anchor {
+            self.list_state.scroll_to(ListOffset {
+                item_ix: self.get_block_index_at_or_after(source_offset),
+                offset_in_item,
+            });
+        } else if MarkdownPreviewSettings::get_global(cx).follow_cursor {
+            // TODO: This does not work as expected.
+            // The scroll request appears to be dropped
+            // after `.reset` is called.
+            self.list_state.scroll_to_reveal_item(self.selected_block);
+        }
+    }
+
+    /// Finds the blocks that the edits made to the buffer since the contents were parsed
+    /// could have changed, unless the document has to be parsed as a whole.
+    fn block_reparse(&self, snapshot: &MultiBufferSnapshot) -> Option<BlockReparse> {
+        if self.contents_have_references {
+            return None;
+        }
+        BlockReparse::new(
+            &self.contents.children,
+            self.contents_source.as_ref()?,
+            snapshot,
+        )
+    }
+
+    /// The project's language registry, used to highlight code blocks, or `None`
+    /// once the workspace has been closed.
+    fn languages(&self, cx: &AppContext) -> Option<Arc<LanguageRegistry>> {
+        let workspace = self.workspace.upgrade()?;
+        let languages = workspace.read(cx).project().read(cx).languages().clone();
+        Some(languages)
+    }
+
+    /// Highlights each code block in the language its fence names, or that
+    /// it was detected to be written in, leaving those in unknown languages as they are.
+    async fn highlight_code_blocks(
+        markdown: &mut ParsedMarkdown,
+        languages: &Arc<LanguageRegistry>,
+    ) {
+        for code_block in markdown.code_blocks_mut() {
+            let Some(language) = &code_block.language else {
+                continue;
+            };
+            let Ok(language) = languages.language_for_name_or_extension(language).await else {
+                continue;
+            };
+            let contents = Rope::from(code_block.contents.as_ref());
+            code_block.highlights = language.highlight_text(&contents, 0..contents.len());
+            code_block.language_name = Some(language.name().to_string().into());
+        }
+    }
+
+    /// Forgets the listed Markdown files of a worktree when any are added to
+    /// or removed from it, so they are listed again by the next parse.
+    fn worktree_entries_changed(&mut self, worktree_id: WorktreeId, changes: &UpdatedEntriesSet) {
+        if self
+            .markdown_files
+            .as_ref()
+            .map_or(true, |(id, _)| *id != worktree_id)
+        {
+            return;
+        }
+
+        let markdown_files_changed = changes.iter().any(|(path, _, change)| {
+            *change != PathChange::Updated
+                && path.extension().map_or(false, |extension| {
+                    extension == "md" || extension == "markdown"
+                })
+        });
+        if markdown_files_changed {
+            self.markdown_files = None;
+        }
+    }
+
+    /// Re-parses the document when the file of one of its images changes, so that
+    /// the image is decoded again.
+    fn reload_changed_images(&mut self, changes: &UpdatedEntriesSet, cx: &mut ViewContext<Self>) {
+        let images = loaded_images(&self.contents.children);
+        let image_changed = changes.iter().any(|(path, _, change)| {
+            *change != PathChange::Loaded
+                && is_image_path(path)
+                && images.keys().any(|source| match source {
+                    MarkdownImageSource::Path(image_path) => image_path.ends_with(path),
+                    MarkdownImageSource::DataUri(_) => false,
+                })
+        });
+        if !image_changed {
+            return;
+        }
+        if let Some(editor) = self
+            .active_editor
+            .as_ref()
+            .map(|state| state.editor.clone())
+        {
+            self.needs_full_parse = true;
+            self.parse_markdown_from_editor(&editor, Duration::ZERO, cx);
+        }
+    }
+
+    /// The Markdown files in the worktree, which `[[wiki links]]` can point to.
+    fn markdown_files(worktree: &Snapshot) -> Vec<PathBuf> {
+        worktree
+            .files(false, 0)
+            .filter(|entry| {
+                entry.path.extension().map_or(false, |extension| {
+                    extension == "md" || extension == "markdown"
+                })
+            })
+            .map(|entry| worktree.abs_path().join(&entry.path))
+            .collect()
+    }
+
+    fn is_markdown_file(editor: &View<Editor>, cx: &AppContext) -> bool {
+        let buffer = editor.read(cx).buffer().read(cx);
+        if let Some(buffer) = buffer.as_singleton() {
+            if let Some(language) = buffer.read(cx).language() {
+                return language.name().as_ref() == "Markdown";
+            }
+        }
+        false
+    }
+
+    /// Copies the images whose paths are on the clipboard into the document's image
+    /// directory, and links to them from the source at the cursor.
+    ///
+    /// The clipboard only holds text, so images are pasted as the paths of their files,
+    /// such as those copied from the project panel or a file manager.
+    fn paste_image(&mut self, _: &PasteImage, cx: &mut ViewContext<Self>) {
+        let Some(editor) = self
+            .active_editor
+            .as_ref()
+            .map(|state| state.editor.clone())
+        else {
+            return;
+        };
+        let Some(document_folder) = Self::get_folder_for_active_editor(editor.read(cx), cx) else {
+            return;
+        };
+        let Some(workspace) = self.workspace.upgrade() else {
+            return;
+        };
+
+        let images: Vec<PathBuf> = cx
+            .read_from_clipboard()
+            .map(|item| {
+                item.text()
+                    .lines()
+                    .map(|line| PathBuf::from(line.trim().trim_start_matches("file://")))
+                    .filter(|path| path.is_absolute() && is_image_path(path))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if images.is_empty() {
+            return;
+        }
+
+        let fs = workspace.read(cx).project().read(cx).fs().clone();
+        let image_directory =
+            document_folder.join(&MarkdownPreviewSettings::get_global(cx).image_directory);
+        cx.spawn(|this, mut cx| async move {
+            let mut links = Vec::new();
+            for image in images {
+                // Images that are already beside the document are linked where they are.
+                let image = if image.starts_with(&document_folder) {
+                    image
+                } else {
+                    Self::copy_image(fs.as_ref(), &image, &image_directory).await?
+                };
+                let relative_path = image.strip_prefix(&document_folder)?;
+                links.push(Self::image_link(relative_path));
+            }
+
+            this.update(&mut cx, |this, cx| {
+                editor.update(cx, |editor, cx| editor.insert(&links.join("\n"), cx));
+                this.parse_markdown_from_editor(&editor, Duration::ZERO, cx);
+            })
         })
+        .detach_and_log_err(cx);
+    }
+
+    /// Copies an image into the given directory, renaming it if the directory
+    /// already has a file with its name, and returns the path of the copy.
+    async fn copy_image(fs: &dyn Fs, image: &Path, directory: &Path) -> anyhow::Result<PathBuf> {
+        let stem = image
+            .file_stem()
+            .context("image path has no file name")?
+            .to_string_lossy();
+        let extension = image
+            .extension()
+            .map(|extension| format!(".{}", extension.to_string_lossy()))
+            .unwrap_or_default();
+
+        let mut target = directory.join(format!("{stem}{extension}"));
+        let mut suffix = 1;
+        while fs.metadata(&target).await?.is_some() {
+            target = directory.join(format!("{stem}-{suffix}{extension}"));
+            suffix += 1;
+        }
+
+        fs.create_dir(directory).await?;
+        fs.copy_file(image, &target, CopyOptions::default()).await?;
+        Ok(target)
+    }
+
+    /// A Markdown image linking to the given path, relative to the document.
+    fn image_link(relative_path: &Path) -> String {
+        let alt_text = relative_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        let path = relative_path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if path.contains(char::is_whitespace) {
+            format!("![{alt_text}](<{path}>)")
+        } else {
+            format!("![{alt_text}]({path})")
+        }
+    }
+
+    fn toggle_pin(&mut self, _: &TogglePreviewPin, cx: &mut ViewContext<Self>) {
+        self.pinned = !self.pinned;
+        cx.notify();
+    }
+
+    pub fn mode(&self) -> PreviewMode {
+        self.mode
+    }
+
+    /// Switches to the given mode, or back to the rendered output if already in it.
+    pub fn toggle_mode(&mut self, mode: PreviewMode, cx: &mut ViewContext<Self>) {
+        self.mode = if self.mode == mode {
+            PreviewMode::Rendered
+        } else {
+            mode
+        };
+        self.update_source_editor(cx);
+        cx.notify();
+    }
+
+    fn toggle_source(&mut self, _: &ToggleSource, cx: &mut ViewContext<Self>) {
+        self.toggle_mode(PreviewMode::Source, cx);
+    }
+
+    fn toggle_split(&mut self, _: &ToggleSplit, cx: &mut ViewContext<Self>) {
+        self.toggle_mode(PreviewMode::Split, cx);
+    }
+
+    /// Creates the source editor when the mode first needs it. It shares the active
+    /// editor's buffer, so it stays in sync with the document without being editable.
+    fn update_source_editor(&mut self, cx: &mut ViewContext<Self>) {
+        if self.mode == PreviewMode::Rendered || self.source_editor.is_some() {
+            return;
+        }
+        let Some(active) = &self.active_editor else {
+            return;
+        };
+
+        let buffer = active.editor.read(cx).buffer().clone();
+        self.source_editor = Some(cx.new_view(|cx| {
+            let mut editor = Editor::for_multibuffer(buffer, None, cx);
+            editor.set_read_only(true);
+            editor
+        }));
+    }
+
+    /// Renders with the settings of the previewed file, so that projects
+    /// can style their documents in their `.zed/settings.json`.
+    fn render_context(&self, cx: &ViewContext<Self>) -> RenderContext {
+        let workspace = Some(self.workspace.clone());
+        let file = self
+            .active_editor
+            .as_ref()
+            .and_then(|state| state.editor.read(cx).file_at(0usize, cx));
+        match file {
+            Some(file) => RenderContext::for_file(workspace, file.as_ref(), cx),
+            None => RenderContext::new(workspace, cx),
+        }
+    }
+
+    fn render_block(&mut self, ix: usize, cx: &mut ViewContext<Self>) -> AnyElement {
+        let started_at = Instant::now();
+        let mut render_cx = self
+            .render_context(cx)
+            .with_font_size_adjustment(self.font_size_adjustment);
+        let block = self.contents.children.get(ix).unwrap();
+        let block = render_markdown_block(block, &mut render_cx);
+        let mut block = div()
+            .child(block)
+            .text_size(render_cx.font_size())
+            .pl_4()
+            .pb(render_cx.block_spacing());
+
+        if ix == self.selected_block {
+            let indicator = div()
+                .h_full()
+                .w(px(4.0))
+                .bg(MarkdownPreviewSettings::get_global(cx)
+                    .preview_theme(cx)
+                    .colors()
+                    .border)
+                .rounded_sm();
+
+            block = div()
+                .relative()
+                .child(block)
+                .child(indicator.absolute().left_0().top_0());
+        }
+
+        if self.is_block_highlighted(ix) {
+            block = block.bg(MarkdownPreviewSettings::get_global(cx)
+                .preview_theme(cx)
+                .players()
+                .local()
+                .selection);
+        }
+
+        let block = block
+            .id(ix)
+            .on_click(cx.listener(move |this, event: &ClickEvent, cx| {
+                this.select_block(ix, event.down.modifiers.shift, cx)
+            }));
+
+        let block = match MarkdownPreviewSettings::get_global(cx).content_width {
+            PreviewContentWidth::FullWidth => block.into_any(),
+            PreviewContentWidth::MaxWidth(max_width) => div()
+                .w_full()
+                .child(block.w_full().max_w(px(max_width)).mx_auto())
+                .into_any(),
+        };
+
+        self.frame_block_count += 1;
+        self.frame_block_time += started_at.elapsed();
+        block
+    }
+
+    /// Shows how long the contents took to parse, and how long building the blocks
+    /// of the previous frame took, so that slow documents can be diagnosed.
+    fn render_performance_overlay(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        // Blocks are built while the list is laid out, after this view has rendered,
+        // so the counts are those of the previous frame.
+        let block_count = mem::take(&mut self.frame_block_count);
+        let block_time = mem::take(&mut self.frame_block_time);
+
+        div()
+            .absolute()
+            .top_0()
+            .right_0()
+            .px_1()
+            .rounded_md()
+            .bg(MarkdownPreviewSettings::get_global(cx)
+                .preview_theme(cx)
+                .colors()
+                .elevated_surface_background)
+            .child(
+                Label::new(format!(
+                    "Parsed in {:.1} ms, built {} blocks in {:.1} ms",
+                    self.parse_time.as_secs_f64() * 1000.,
+                    block_count,
+                    block_time.as_secs_f64() * 1000.,
+                ))
+                .size(LabelSize::XSmall)
+                .color(Color::Muted),
+            )
+    }
+
+    fn zoom_in(&mut self, _: &ZoomIn, cx: &mut ViewContext<Self>) {
+        self.adjust_font_size(px(1.), cx);
+    }
+
+    fn zoom_out(&mut self, _: &ZoomOut, cx: &mut ViewContext<Self>) {
+        self.adjust_font_size(px(-1.), cx);
+    }
+
+    fn reset_zoom(&mut self, _: &ResetZoom, cx: &mut ViewContext<Self>) {
+        self.font_size_adjustment = px(0.);
+        self.list_state.reset(self.contents.children.len());
+        cx.notify();
+    }
+
+    fn adjust_font_size(&mut self, delta: Pixels, cx: &mut ViewContext<Self>) {
+        self.font_size_adjustment += delta;
+        // Every block changes height when the font size changes,
+        // so the cached measurements need to be thrown away.
+        self.list_state.reset(self.contents.children.len());
+        cx.notify();
     }
 
     /// The absolute path of the file that is currently being previewed.
@@ -145,6 +1600,27 @@ impl MarkdownPreviewView {
         }
     }
 
+    /// Where the block at the top of the preview starts in the given snapshot of its source,
+    /// which may have been edited since the contents were parsed, along with how far
+    /// the preview is scrolled into that block.
+    fn scroll_anchor(&self, snapshot: &MultiBufferSnapshot) -> Option<(usize, Pixels)> {
+        let contents_source = self.contents_source.as_ref()?;
+        let scroll_top = self.list_state.logical_scroll_top();
+        let block = self.contents.children.get(scroll_top.item_ix)?;
+        let anchor = contents_source.anchor_before(block.source_range().start);
+        Some((anchor.to_offset(snapshot), scroll_top.offset_in_item))
+    }
+
+    /// The first block that ends at or after the given offset in the source,
+    /// so offsets between blocks resolve to the block that follows them.
+    fn get_block_index_at_or_after(&self, offset: usize) -> usize {
+        self.contents
+            .children
+            .iter()
+            .position(|block| block.source_range().end >= offset)
+            .unwrap_or(self.contents.children.len().saturating_sub(1))
+    }
+
     fn get_block_index_under_cursor(&self, selection_range: Range<usize>) -> usize {
         let mut block_index = 0;
         let cursor = selection_range.start;
@@ -168,7 +1644,12 @@ impl FocusableView for MarkdownPreviewView {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub enum PreviewEvent {}
+pub enum PreviewEvent {
+    /// The heading the preview is scrolled to has changed.
+    ScrolledToHeading,
+    /// The previewed document, or its title or dirty state, has changed.
+    SourceChanged,
+}
 
 impl EventEmitter<PreviewEvent> for MarkdownPreviewView {}
 
@@ -179,20 +1660,52 @@ impl Item for MarkdownPreviewView {
         &self,
         _detail: Option<usize>,
         selected: bool,
-        _cx: &WindowContext,
+        cx: &WindowContext,
     ) -> AnyElement {
+        let icon = if self.pinned {
+            IconName::FileLock
+        } else {
+            IconName::FileDoc
+        };
+
+        let source_editor = self
+            .active_editor
+            .as_ref()
+            .map(|active| active.editor.clone());
+
+        // Middle-clicking or cmd-clicking the tab jumps to the source editor,
+        // so the pane's own click handling is skipped for those clicks.
         h_flex()
+            .id("markdown-preview-tab")
             .gap_2()
-            .child(Icon::new(IconName::FileDoc).color(if selected {
+            .child(Icon::new(icon).color(if selected {
                 Color::Default
             } else {
                 Color::Muted
             }))
-            .child(Label::new("Markdown preview").color(if selected {
+            .child(Label::new(self.title(cx)).color(if selected {
                 Color::Default
             } else {
                 Color::Muted
             }))
+            .when(self.is_source_dirty(cx), |this| {
+                this.child(Indicator::dot().color(Color::Accent))
+            })
+            .when_some(source_editor, |this, editor| {
+                let workspace = self.workspace.clone();
+                let middle_click_editor = editor.clone();
+                let middle_click_workspace = workspace.clone();
+                this.on_mouse_down(MouseButton::Middle, move |_, cx| {
+                    cx.stop_propagation();
+                    Self::open_source_editor(&middle_click_workspace, &middle_click_editor, cx);
+                })
+                .on_click(move |event, cx| {
+                    if event.down.modifiers.command {
+                        cx.stop_propagation();
+                        Self::open_source_editor(&workspace, &editor, cx);
+                    }
+                })
+            })
             .into_any()
     }
 
@@ -200,7 +1713,90 @@ impl Item for MarkdownPreviewView {
         Some("markdown preview")
     }
 
-    fn to_item_events(_event: &Self::Event, _f: impl FnMut(workspace::item::ItemEvent)) {}
+    fn to_item_events(event: &Self::Event, mut f: impl FnMut(ItemEvent)) {
+        match event {
+            PreviewEvent::ScrolledToHeading => f(ItemEvent::UpdateBreadcrumbs),
+            PreviewEvent::SourceChanged => f(ItemEvent::UpdateTab),
+        }
+    }
+
+    fn breadcrumb_location(&self) -> ToolbarItemLocation {
+        ToolbarItemLocation::PrimaryLeft
+    }
+
+    /// Shows the headings that contain the topmost visible block,
+    /// from the outermost heading to the innermost one.
+    fn breadcrumbs(&self, _theme: &Theme, _cx: &AppContext) -> Option<Vec<BreadcrumbText>> {
+        let mut headings: Vec<&ParsedMarkdownHeading> = vec![];
+        for block in self
+            .contents
+            .children
+            .iter()
+            .take(self.top_visible_block + 1)
+        {
+            if let ParsedMarkdownElement::Heading(heading) = block {
+                while headings
+                    .last()
+                    .map_or(false, |parent| parent.level >= heading.level)
+                {
+                    headings.pop();
+                }
+                headings.push(heading);
+            }
+        }
+
+        Some(
+            headings
+                .into_iter()
+                .map(|heading| BreadcrumbText {
+                    text: heading.contents.contents.clone(),
+                    highlights: None,
+                })
+                .collect(),
+        )
+    }
+
+    fn added_to_workspace(&mut self, workspace: &mut Workspace, cx: &mut ViewContext<Self>) {
+        self.workspace_id = workspace.is_serializable().then(|| workspace.database_id());
+        self.serialize(cx);
+    }
+
+    fn serialized_item_kind() -> Option<&'static str> {
+        Some("MarkdownPreview")
+    }
+
+    fn deserialize(
+        project: Model<Project>,
+        workspace: WeakView<Workspace>,
+        workspace_id: WorkspaceId,
+        item_id: ItemId,
+        cx: &mut ViewContext<Pane>,
+    ) -> Task<anyhow::Result<View<Self>>> {
+        let preview = MARKDOWN_PREVIEW_DB
+            .get_preview(item_id, workspace_id)
+            .and_then(|preview| preview.context("No path stored for this markdown preview"));
+        let (path, scroll_top) = match preview {
+            Ok((path, scroll_top)) => (path, scroll_top as usize),
+            Err(error) => return Task::ready(Err(error)),
+        };
+        let open_buffer = Self::open_buffer(&project, &path, cx);
+
+        cx.spawn(|pane, mut cx| async move {
+            let buffer = open_buffer.await?;
+
+            pane.update(&mut cx, |_, cx| {
+                // The preview is restored on its own, so it renders the document
+                // through an editor that isn't shown in any pane.
+                let editor = cx.new_view(|cx| Editor::for_buffer(buffer, Some(project), cx));
+                let preview = MarkdownPreviewView::new(editor, workspace, cx);
+                preview.update(cx, |preview, _| {
+                    preview.scroll_positions.insert(path, scroll_top);
+                    preview.pending_scroll_top = Some(scroll_top);
+                });
+                preview
+            })
+        })
+    }
 }
 
 impl Render for MarkdownPreviewView {
@@ -209,13 +1805,185 @@ impl Render for MarkdownPreviewView {
             .id("MarkdownPreview")
             .key_context("MarkdownPreview")
             .track_focus(&self.focus_handle)
+            .on_action(cx.listener(Self::zoom_in))
+            .on_action(cx.listener(Self::zoom_out))
+            .on_action(cx.listener(Self::reset_zoom))
+            .on_action(cx.listener(Self::toggle_pin))
+            .on_action(cx.listener(Self::paste_image))
+            .on_action(cx.listener(Self::toggle_source))
+            .on_action(cx.listener(Self::toggle_split))
+            .on_action(cx.listener(Self::refresh))
+            .on_action(cx.listener(Self::scroll_page_up))
+            .on_action(cx.listener(Self::scroll_page_down))
+            .on_action(cx.listener(Self::scroll_to_top))
+            .on_action(cx.listener(Self::scroll_to_bottom))
+            .on_action(cx.listener(Self::next_heading))
+            .on_action(cx.listener(Self::previous_heading))
             .full()
-            .bg(cx.theme().colors().editor_background)
+            .bg(MarkdownPreviewSettings::get_global(cx)
+                .preview_theme(cx)
+                .colors()
+                .editor_background)
             .p_4()
+            .when(self.needs_refresh, |this| {
+                this.child(
+                    h_flex()
+                        .pb_2()
+                        .gap_2()
+                        .justify_between()
+                        .child(
+                            Label::new("The document has changed since it was rendered")
+                                .size(LabelSize::Small)
+                                .color(Color::Muted),
+                        )
+                        .child(
+                            Button::new("refresh-markdown-preview", "Refresh")
+                                .label_size(LabelSize::Small)
+                                .on_click(cx.listener(|this, _, cx| this.refresh(&Refresh, cx))),
+                        ),
+                )
+            })
             .child(
-                div()
+                h_flex()
                     .flex_grow()
-                    .map(|this| this.child(list(self.list_state.clone()).full())),
+                    .gap_4()
+                    .items_start()
+                    .when_some(
+                        self.source_editor
+                            .clone()
+                            .filter(|_| self.mode != PreviewMode::Rendered),
+                        |this, source_editor| {
+                            this.child(
+                                div()
+                                    .h_full()
+                                    .flex_1()
+                                    .overflow_hidden()
+                                    .child(source_editor),
+                            )
+                        },
+                    )
+                    .when(self.mode != PreviewMode::Source, |this| {
+                        this.child(
+                            div()
+                                .relative()
+                                .h_full()
+                                .flex_1()
+                                .map(|this| this.child(list(self.list_state.clone()).full()))
+                                .when(
+                                    MarkdownPreviewSettings::get_global(cx).performance_overlay,
+                                    |this| this.child(self.render_performance_overlay(cx)),
+                                ),
+                        )
+                    }),
             )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use editor::MultiBuffer;
+
+    use super::*;
+
+    const TEXT: &str = "# Title\n\nFirst.\n\nSecond.\n\nThird.\n\nLast.\n";
+
+    fn parse(text: &str) -> ParsedMarkdown {
+        parse_markdown(text, None, &[], false, false)
+    }
+
+    /// Makes the edits to the text and re-parses the blocks around them, asserting
+    /// that this parses the same as the whole edited text. Returns whether only
+    /// the blocks were re-parsed, rather than the whole document.
+    fn reparse_blocks(text: &str, edits: Vec<(Range<usize>, &str)>, cx: &mut AppContext) -> bool {
+        let buffer = MultiBuffer::build_simple(text, cx);
+        let old_snapshot = buffer.read(cx).snapshot(cx);
+        let mut contents = parse(text);
+        buffer.update(cx, |buffer, cx| buffer.edit(edits, None, cx));
+        let snapshot = buffer.read(cx).snapshot(cx);
+        let new_text = snapshot.text();
+
+        let reparsed = BlockReparse::new(&contents.children, &old_snapshot, &snapshot)
+            .and_then(|block_reparse| block_reparse.parse(&snapshot, parse));
+        let reparsed_blocks = match reparsed {
+            Some(ParsedContents::Blocks {
+                replaced,
+                blocks,
+                delta,
+            }) => {
+                contents.splice(replaced, blocks.children, delta);
+                true
+            }
+            Some(ParsedContents::Document { .. }) | None => {
+                contents = parse(&new_text);
+                false
+            }
+        };
+
+        assert_eq!(
+            contents.children,
+            parse(&new_text).children,
+            "re-parsing {new_text:?}"
+        );
+        reparsed_blocks
+    }
+
+    #[gpui::test]
+    fn test_reparsing_blocks_around_edits(cx: &mut AppContext) {
+        // Editing the first or last block, which only have a block on one side.
+        assert!(reparse_blocks(TEXT, vec![(0..1, "")], cx));
+        assert!(reparse_blocks(TEXT, vec![(34..34, "- ")], cx));
+        assert!(reparse_blocks(TEXT, vec![(38..39, "")], cx));
+
+        // Merging blocks by removing the blank line between them.
+        assert!(reparse_blocks(TEXT, vec![(25..26, "")], cx));
+        assert!(reparse_blocks(TEXT, vec![(8..9, "")], cx));
+        assert!(reparse_blocks(
+            "Intro.\n\n> quote\n\nText.\n\nOutro.\n",
+            vec![(16..17, "")],
+            cx
+        ));
+
+        // Splitting a block in two.
+        assert!(reparse_blocks(TEXT, vec![(20..20, "\n\n")], cx));
+
+        // Adding a block, or more blank lines, between blocks.
+        assert!(reparse_blocks(TEXT, vec![(25..26, "\nBetween.\n\n")], cx));
+        assert!(reparse_blocks(TEXT, vec![(25..25, "\n\n")], cx));
+
+        // Removing a block along with the blank line after it.
+        assert!(reparse_blocks(TEXT, vec![(17..26, "")], cx));
+
+        // Edits to separate blocks re-parse every block between them.
+        assert!(reparse_blocks(
+            TEXT,
+            vec![(9..15, "Start"), (34..38, "End")],
+            cx
+        ));
+    }
+
+    #[gpui::test]
+    fn test_reparsing_blocks_that_change_their_neighbors(cx: &mut AppContext) {
+        // The edited paragraph becomes the first item of the list after it,
+        // which then starts earlier.
+        assert!(!reparse_blocks(
+            "Intro.\n\nText.\n\n- two\n\nOutro.\n",
+            vec![(8..8, "- ")],
+            cx
+        ));
+
+        // The edited heading becomes a paragraph that continues the list before it.
+        assert!(!reparse_blocks(
+            "Intro.\n\n- one\n# Heading\n\nOutro.\n",
+            vec![(15..16, "")],
+            cx
+        ));
+
+        // Fenced code and reference definitions can change the rest of the document.
+        assert!(!reparse_blocks(TEXT, vec![(17..17, "```\n")], cx));
+        assert!(!reparse_blocks(
+            TEXT,
+            vec![(26..26, "[zed]: https://zed.dev\n\n")],
+            cx
+        ));
+    }
+}
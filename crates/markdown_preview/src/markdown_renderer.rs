@@ -1,55 +1,236 @@
-use crate::markdown_elements::{
-    HeadingLevel, Link, ParsedMarkdown, ParsedMarkdownBlockQuote, ParsedMarkdownCodeBlock,
-    ParsedMarkdownElement, ParsedMarkdownHeading, ParsedMarkdownList, ParsedMarkdownListItemType,
-    ParsedMarkdownTable, ParsedMarkdownTableAlignment, ParsedMarkdownTableRow, ParsedMarkdownText,
+use crate::{
+    markdown_elements::{
+        HeadingLevel, Link, MarkdownHighlight, ParsedMarkdown, ParsedMarkdownBlockQuote,
+        ParsedMarkdownCodeBlock, ParsedMarkdownElement, ParsedMarkdownHeading, ParsedMarkdownList,
+        ParsedMarkdownListItem, ParsedMarkdownListItemType, ParsedMarkdownTable,
+        ParsedMarkdownTableAlignment, ParsedMarkdownTableRow, ParsedMarkdownText, ParsedRegion,
+    },
+    markdown_parser::parse_markdown,
+    markdown_preview_settings::{HeadingScale, MarkdownPreviewSettings},
 };
+use editor::Editor;
 use gpui::{
-    div, px, rems, AbsoluteLength, AnyElement, DefiniteLength, Div, Element, ElementId,
-    HighlightStyle, Hsla, InteractiveText, IntoElement, ParentElement, SharedString, Styled,
-    StyledText, TextStyle, WeakView, WindowContext,
+    div, img, prelude::FluentBuilder, px, relative, rems, AnyElement, AnyView, ClipboardItem,
+    DefiniteLength, Div, Element, ElementId, HighlightStyle, Hsla, InteractiveElement,
+    InteractiveText, IntoElement, ParentElement, Pixels, Rems, Render, SharedString,
+    StatefulInteractiveElement, Styled, StyledText, TextStyle, UnderlineStyle, ViewContext,
+    VisualContext, WeakView, WindowContext, WindowTextSystem,
 };
-use std::{ops::Range, sync::Arc};
-use theme::{ActiveTheme, SyntaxTheme};
-use ui::{h_flex, v_flex, Label};
+use settings::Settings;
+use std::{ops::Range, rc::Rc, sync::Arc};
+use theme::{SyntaxTheme, ThemeRegistry};
+use ui::{
+    h_flex, tooltip_container, v_flex, ButtonCommon, Clickable, Color, IconButton, IconName,
+    IconSize, Tooltip, VisibleOnHover,
+};
+use util::ResultExt;
 use workspace::Workspace;
 
+const MIN_FONT_SIZE: Pixels = px(6.0);
+
+/// Renders parsed Markdown, styled after the markdown preview's settings.
+///
+/// Views other than the preview, such as hover popovers, can render Markdown
+/// with it by giving it their own id prefix, sizing and link handling.
 pub struct RenderContext {
     workspace: Option<WeakView<Workspace>>,
+    /// Prefixes the ids of rendered elements, so that several documents
+    /// can be rendered in the same view without their ids colliding.
+    id_prefix: SharedString,
     next_id: usize,
+    /// Handles clicked links, instead of them being opened in the workspace.
+    link_handler: Option<Rc<dyn Fn(&Link, &mut WindowContext)>>,
+    font_size: Pixels,
     text_style: TextStyle,
+    code_text_style: TextStyle,
     border_color: Hsla,
     text_color: Hsla,
     text_muted_color: Hsla,
     code_block_background_color: Hsla,
     code_span_background_color: Hsla,
+    highlight_background_color: Hsla,
+    broken_link_color: Hsla,
+    task_progress_color: Hsla,
+    heading_scale: HeadingScale,
+    heading_color: Option<Hsla>,
+    spacing_scale: f32,
     syntax_theme: Arc<SyntaxTheme>,
+    /// Looks up the themes that fences ask to be highlighted with.
+    theme_registry: Arc<ThemeRegistry>,
+    /// Measures text, such as to size the columns of tables.
+    text_system: Arc<WindowTextSystem>,
     indent: usize,
+    block_quote_depth: usize,
+    ordered_list_depth: u16,
+    code_block_line_numbers: bool,
 }
 
 impl RenderContext {
     pub fn new(workspace: Option<WeakView<Workspace>>, cx: &WindowContext) -> RenderContext {
-        let theme = cx.theme().clone();
+        Self::with_settings(workspace, MarkdownPreviewSettings::get_global(cx), cx)
+    }
+
+    /// Renders with the settings that apply to the given file,
+    /// including those in its project's `.zed/settings.json`.
+    pub fn for_file(
+        workspace: Option<WeakView<Workspace>>,
+        file: &dyn language::File,
+        cx: &WindowContext,
+    ) -> RenderContext {
+        let settings = MarkdownPreviewSettings::get(Some((file.worktree_id(), file.path())), cx);
+        Self::with_settings(workspace, settings, cx)
+    }
+
+    fn with_settings(
+        workspace: Option<WeakView<Workspace>>,
+        settings: &MarkdownPreviewSettings,
+        cx: &WindowContext,
+    ) -> RenderContext {
+        let theme = settings.preview_theme(cx);
+        let style = &settings.style;
+
+        let mut text_style = cx.text_style();
+        text_style.color = theme.colors().text;
+        let font_size = settings
+            .font_size
+            .unwrap_or_else(|| text_style.font_size.to_pixels(cx.rem_size()));
+        text_style.font_size = font_size.into();
+        if let Some(font_family) = &style.font_family {
+            text_style.font_family = font_family.clone().into();
+        }
+        let mut code_text_style = text_style.clone();
+        if let Some(code_font_family) = &style.code_font_family {
+            code_text_style.font_family = code_font_family.clone().into();
+        }
+
+        let colors = theme.colors();
+        let minimum_contrast = settings.minimum_contrast;
+        // Code backgrounds are often translucent, so they are measured as they
+        // appear over the preview's background.
+        let code_background = |color: Hsla| {
+            let opaque = colors.editor_background.blend(color);
+            if contrast_ratio(colors.text, opaque) >= minimum_contrast {
+                color
+            } else {
+                with_minimum_contrast(opaque, colors.text, minimum_contrast)
+            }
+        };
 
         RenderContext {
             workspace,
+            id_prefix: "markdown".into(),
             next_id: 0,
+            link_handler: None,
             indent: 0,
-            text_style: cx.text_style(),
-            syntax_theme: theme.syntax().clone(),
+            block_quote_depth: 0,
+            ordered_list_depth: 0,
+            code_block_line_numbers: settings.code_block_line_numbers,
+            font_size,
+            text_style,
+            code_text_style,
+            syntax_theme: settings.code_block_syntax_theme(cx),
+            theme_registry: ThemeRegistry::global(cx),
+            text_system: cx.text_system().clone(),
             border_color: theme.colors().border,
             text_color: theme.colors().text,
-            text_muted_color: theme.colors().text_muted,
-            code_block_background_color: theme.colors().surface_background,
-            code_span_background_color: theme.colors().editor_document_highlight_read_background,
+            text_muted_color: with_minimum_contrast(
+                colors.text_muted,
+                colors.editor_background,
+                minimum_contrast,
+            ),
+            code_block_background_color: style
+                .code_block_background
+                .unwrap_or_else(|| code_background(colors.surface_background)),
+            code_span_background_color: style.code_span_background.unwrap_or_else(|| {
+                code_background(colors.editor_document_highlight_read_background)
+            }),
+            highlight_background_color: theme.colors().search_match_background,
+            broken_link_color: theme.status().warning,
+            task_progress_color: theme.status().success,
+            heading_scale: settings.heading_scale,
+            heading_color: style.heading_color,
+            spacing_scale: style.spacing_scale,
         }
     }
 
+    /// Prefixes the ids of the rendered elements, which defaults to `markdown`.
+    pub fn with_id_prefix(mut self, id_prefix: impl Into<SharedString>) -> Self {
+        self.id_prefix = id_prefix.into();
+        self
+    }
+
+    /// Handles clicked links with the given callback, rather than opening web links
+    /// in the browser and paths in the workspace.
+    pub fn with_link_handler(
+        mut self,
+        link_handler: impl Fn(&Link, &mut WindowContext) + 'static,
+    ) -> Self {
+        self.link_handler = Some(Rc::new(link_handler));
+        self
+    }
+
+    /// Sets the base font size of the rendered Markdown, from which
+    /// the sizes of headings and code are derived.
+    pub fn with_font_size(mut self, font_size: Pixels) -> Self {
+        self.font_size = font_size.max(MIN_FONT_SIZE);
+        self.text_style.font_size = self.font_size.into();
+        self.code_text_style.font_size = self.font_size.into();
+        self
+    }
+
+    /// Offsets the base font size of the rendered Markdown,
+    /// for example when the user zooms the preview in or out.
+    pub fn with_font_size_adjustment(mut self, adjustment: Pixels) -> Self {
+        self.font_size = (self.font_size + adjustment).max(MIN_FONT_SIZE);
+        self.text_style.font_size = self.font_size.into();
+        self.code_text_style.font_size = self.font_size.into();
+        self
+    }
+
+    /// The base font size that Markdown text is rendered with.
+    pub fn font_size(&self) -> Pixels {
+        self.font_size
+    }
+
+    /// Renders each block of the document.
+    pub fn render(&mut self, parsed: &ParsedMarkdown) -> Vec<AnyElement> {
+        parsed
+            .children
+            .iter()
+            .map(|block| render_markdown_block(block, self))
+            .collect()
+    }
+
     fn next_id(&mut self, span: &Range<usize>) -> ElementId {
-        let id = format!("markdown-{}-{}-{}", self.next_id, span.start, span.end);
+        let id = format!(
+            "{}-{}-{}-{}",
+            self.id_prefix, self.next_id, span.start, span.end
+        );
         self.next_id += 1;
         ElementId::from(SharedString::from(id))
     }
 
+    /// The space left below each block.
+    pub fn block_spacing(&self) -> Rems {
+        rems(0.75 * self.spacing_scale)
+    }
+
+    /// The width of the widest line of the text, when laid out in the text style.
+    fn text_width(&self, text: &str) -> Pixels {
+        text.lines()
+            .filter_map(|line| {
+                let run = self.text_style.to_run(line.len());
+                // Text that can't be laid out is left unmeasured rather than logged,
+                // as it'd be logged on every frame.
+                self.text_system
+                    .layout_line(line, self.font_size, &[run])
+                    .ok()
+            })
+            .map(|layout| layout.width)
+            .max()
+            .unwrap_or(px(0.))
+    }
+
     /// This ensures that children inside of block quotes
     /// have padding between them.
     ///
@@ -65,7 +246,7 @@ impl RenderContext {
     /// and "And this is the next paragraph."
     fn with_common_p(&self, element: Div) -> Div {
         if self.indent > 0 {
-            element.pb_3()
+            element.pb(self.block_spacing())
         } else {
             element
         }
@@ -77,14 +258,7 @@ pub fn render_parsed_markdown(
     workspace: Option<WeakView<Workspace>>,
     cx: &WindowContext,
 ) -> Vec<AnyElement> {
-    let mut cx = RenderContext::new(workspace, cx);
-    let mut elements = Vec::new();
-
-    for child in &parsed.children {
-        elements.push(render_markdown_block(child, &mut cx));
-    }
-
-    return elements;
+    RenderContext::new(workspace, cx).render(parsed)
 }
 
 pub fn render_markdown_block(block: &ParsedMarkdownElement, cx: &mut RenderContext) -> AnyElement {
@@ -101,21 +275,14 @@ pub fn render_markdown_block(block: &ParsedMarkdownElement, cx: &mut RenderConte
 }
 
 fn render_markdown_heading(parsed: &ParsedMarkdownHeading, cx: &mut RenderContext) -> AnyElement {
-    let size = match parsed.level {
-        HeadingLevel::H1 => rems(2.),
-        HeadingLevel::H2 => rems(1.5),
-        HeadingLevel::H3 => rems(1.25),
-        HeadingLevel::H4 => rems(1.),
-        HeadingLevel::H5 => rems(0.875),
-        HeadingLevel::H6 => rems(0.85),
-    };
+    let size = cx.font_size * cx.heading_scale.for_level(&parsed.level);
 
-    let color = match parsed.level {
+    let color = cx.heading_color.unwrap_or(match parsed.level {
         HeadingLevel::H6 => cx.text_muted_color,
         _ => cx.text_color,
-    };
+    });
 
-    let line_height = DefiniteLength::from(rems(1.25));
+    let line_height = DefiniteLength::from(cx.font_size * 1.25);
 
     div()
         .line_height(line_height)
@@ -123,6 +290,10 @@ fn render_markdown_heading(parsed: &ParsedMarkdownHeading, cx: &mut RenderContex
         .text_color(color)
         .pt(rems(0.15))
         .pb_1()
+        // As with paragraphs, a heading that wraps stays aligned left.
+        .when(parsed.contents.is_right_to_left(), |this| {
+            this.flex().justify_end()
+        })
         .child(render_markdown_text(&parsed.contents, cx))
         .into_any()
 }
@@ -130,17 +301,54 @@ fn render_markdown_heading(parsed: &ParsedMarkdownHeading, cx: &mut RenderContex
 fn render_markdown_list(parsed: &ParsedMarkdownList, cx: &mut RenderContext) -> AnyElement {
     use ParsedMarkdownListItemType::*;
 
+    let is_ordered = parsed
+        .children
+        .iter()
+        .any(|item| matches!(item.item_type, Ordered(_)));
+    if is_ordered {
+        cx.ordered_list_depth += 1;
+    }
+    let ordered_list_depth = cx.ordered_list_depth;
+
+    let bullets: Vec<String> = parsed
+        .children
+        .iter()
+        .map(|item| list_item_marker(item, ordered_list_depth))
+        .collect();
+
+    // Size the marker column for the widest marker, so that the contents
+    // of items numbered `9.` and `10.` stay aligned with each other.
+    let bullet_width = bullets
+        .iter()
+        .map(|bullet| bullet.chars().count())
+        .max()
+        .unwrap_or(1);
+    let bullet_width = rems(0.6 * bullet_width as f32);
+
     let mut items = vec![];
-    for item in &parsed.children {
-        let padding = rems((item.depth - 1) as f32 * 0.25);
+    for (item, bullet) in parsed.children.iter().zip(bullets) {
+        // Items written right to left have their markers on the right.
+        let is_right_to_left = item
+            .contents
+            .first()
+            .map_or(false, |block| block.is_right_to_left());
 
-        let bullet = match item.item_type {
-            Ordered(order) => format!("{}.", order),
-            Unordered => "•".to_string(),
-            Task(checked) => if checked { "☑" } else { "☐" }.to_string(),
-        };
-        let bullet = div().mr_2().child(Label::new(bullet));
+        let bullet = div()
+            .flex_none()
+            .min_w(bullet_width)
+            .map(|this| {
+                if is_right_to_left {
+                    this.ml_2()
+                } else {
+                    this.mr_2()
+                }
+            })
+            .text_ui()
+            .text_color(cx.text_color)
+            .child(bullet);
 
+        // Nested lists, code blocks and block quotes are rendered in the
+        // contents column, so they're indented underneath the item's text.
         let contents: Vec<AnyElement> = item
             .contents
             .iter()
@@ -148,35 +356,227 @@ fn render_markdown_list(parsed: &ParsedMarkdownList, cx: &mut RenderContext) ->
             .collect();
 
         let item = h_flex()
-            .pl(DefiniteLength::Absolute(AbsoluteLength::Rems(padding)))
             .items_start()
-            .children(vec![bullet, div().children(contents).pr_2().w_full()]);
+            .when(is_right_to_left, |this| this.flex_row_reverse())
+            .child(bullet)
+            .child(v_flex().gap_1().pr_2().w_full().children(contents));
 
         items.push(item);
     }
 
-    cx.with_common_p(div()).children(items).into_any()
+    if is_ordered {
+        cx.ordered_list_depth -= 1;
+    }
+
+    // Nested lists are counted towards the progress of the list they're in.
+    let is_nested = parsed.children.first().map_or(false, |item| item.depth > 1);
+    let progress = match parsed.task_progress() {
+        (completed, total) if total > 0 && !is_nested => {
+            Some(render_task_progress(completed, total, cx))
+        }
+        _ => None,
+    };
+
+    cx.with_common_p(v_flex())
+        .gap_1()
+        .children(progress)
+        .children(items)
+        .into_any()
+}
+
+/// A summary of how many of a task list's tasks are done, with a progress bar.
+fn render_task_progress(completed: usize, total: usize, cx: &RenderContext) -> AnyElement {
+    let bar = div()
+        .w(rems(8.))
+        .h(px(4.))
+        .rounded_sm()
+        .bg(cx.border_color)
+        .child(
+            div()
+                .h_full()
+                .rounded_sm()
+                .w(relative(completed as f32 / total as f32))
+                .bg(cx.task_progress_color),
+        );
+
+    h_flex()
+        .gap_2()
+        .mb_1()
+        .child(
+            div()
+                .text_ui_sm()
+                .text_color(cx.text_muted_color)
+                .child(format!("{completed} of {total} tasks done")),
+        )
+        .child(bar)
+        .into_any()
+}
+
+/// The marker shown before a list item, given how many ordered lists
+/// its list is nested in, including itself.
+fn list_item_marker(item: &ParsedMarkdownListItem, ordered_list_depth: u16) -> String {
+    use ParsedMarkdownListItemType::*;
+
+    match item.item_type {
+        Ordered(order) => format!("{}.", ordered_list_marker(order, ordered_list_depth)),
+        Unordered => match item.depth % 3 {
+            1 => "•",
+            2 => "◦",
+            _ => "▪",
+        }
+        .to_string(),
+        Task(checked) => if checked { "☑" } else { "☐" }.to_string(),
+    }
+}
+
+/// Formats the number of an ordered list item in the conventional style
+/// for how deeply its list is nested inside other ordered lists,
+/// cycling through `1.`, `a.` and `i.`.
+fn ordered_list_marker(order: u64, depth: u16) -> String {
+    match depth % 3 {
+        1 => order.to_string(),
+        2 => to_alphabetic(order),
+        _ => to_roman(order),
+    }
+}
+
+fn to_alphabetic(mut number: u64) -> String {
+    if number == 0 {
+        return number.to_string();
+    }
+
+    let mut letters = Vec::new();
+    while number > 0 {
+        number -= 1;
+        letters.push((b'a' + (number % 26) as u8) as char);
+        number /= 26;
+    }
+    letters.into_iter().rev().collect()
 }
 
+fn to_roman(mut number: u64) -> String {
+    const NUMERALS: [(u64, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+
+    if number == 0 || number >= 4000 {
+        return number.to_string();
+    }
+
+    let mut roman = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            roman.push_str(numeral);
+            number -= value;
+        }
+    }
+    roman
+}
+
+/// The widest a table column grows, as a multiple of the font size, before its contents wrap.
+const MAX_TABLE_COLUMN_WIDTH: f32 = 24.;
+/// The narrowest a table column is made, as a multiple of the font size.
+const MIN_TABLE_COLUMN_WIDTH: f32 = 2.;
+/// The widest a table grows, as a multiple of the font size, before its columns
+/// are narrowed in proportion to their widths. Tables with too many columns
+/// to narrow that far scroll horizontally instead.
+const MAX_TABLE_WIDTH: f32 = 60.;
+
 fn render_markdown_table(parsed: &ParsedMarkdownTable, cx: &mut RenderContext) -> AnyElement {
-    let header = render_markdown_table_row(&parsed.header, &parsed.column_alignments, true, cx);
+    let column_widths = table_column_widths(parsed, cx);
+
+    let header = render_markdown_table_row(
+        &parsed.header,
+        &parsed.column_alignments,
+        &column_widths,
+        true,
+        cx,
+    );
 
     let body: Vec<AnyElement> = parsed
         .body
         .iter()
-        .map(|row| render_markdown_table_row(row, &parsed.column_alignments, false, cx))
+        .map(|row| {
+            render_markdown_table_row(row, &parsed.column_alignments, &column_widths, false, cx)
+        })
         .collect();
 
+    let caption = parsed.caption.as_ref().map(|caption| {
+        div()
+            .pt_1()
+            .text_color(cx.text_muted_color)
+            .child(render_markdown_text(caption, cx))
+    });
+
     cx.with_common_p(v_flex())
-        .w_full()
-        .child(header)
-        .children(body)
+        .child(
+            div()
+                .id(cx.next_id(&parsed.source_range))
+                .w_full()
+                .overflow_x_scroll()
+                .child(v_flex().flex_none().child(header).children(body)),
+        )
+        .children(caption)
         .into_any()
 }
 
+/// Sizes each column to fit its widest cell, so that short columns stay narrow
+/// and long ones wrap once they reach [`MAX_TABLE_COLUMN_WIDTH`]. Columns are then
+/// narrowed in proportion to their widths to fit the table in [`MAX_TABLE_WIDTH`].
+fn table_column_widths(parsed: &ParsedMarkdownTable, cx: &RenderContext) -> Vec<Pixels> {
+    let mut column_widths: Vec<Pixels> = vec![];
+    for row in std::iter::once(&parsed.header).chain(&parsed.body) {
+        for (ix, cell) in row.children.iter().enumerate() {
+            let width = cx.text_width(&cell.contents);
+            if ix < column_widths.len() {
+                column_widths[ix] = column_widths[ix].max(width);
+            } else {
+                column_widths.push(width);
+            }
+        }
+    }
+
+    let min_width = cx.font_size * MIN_TABLE_COLUMN_WIDTH;
+    let max_width = cx.font_size * MAX_TABLE_COLUMN_WIDTH;
+    for width in &mut column_widths {
+        *width = (*width).clamp(min_width, max_width);
+    }
+
+    let total_width = column_widths
+        .iter()
+        .fold(px(0.), |total, width| total + *width);
+    let max_total_width = cx.font_size * MAX_TABLE_WIDTH;
+    if total_width > max_total_width {
+        let scale = max_total_width / total_width;
+        for width in &mut column_widths {
+            *width = (*width * scale).max(min_width);
+        }
+    }
+
+    // Leave room for the cell's horizontal padding and borders.
+    let padding = cx.font_size * 1.5;
+    column_widths
+        .into_iter()
+        .map(|width| width + padding)
+        .collect()
+}
+
 fn render_markdown_table_row(
     parsed: &ParsedMarkdownTableRow,
     alignments: &Vec<ParsedMarkdownTableAlignment>,
+    column_widths: &[Pixels],
     is_header: bool,
     cx: &mut RenderContext,
 ) -> AnyElement {
@@ -187,22 +587,28 @@ fn render_markdown_table_row(
             .get(items.len())
             .copied()
             .unwrap_or(ParsedMarkdownTableAlignment::None);
+        let width = column_widths.get(items.len()).copied();
 
-        let contents = render_markdown_text(cell, cx);
+        let contents = render_markdown_text_with_images(cell, cx);
 
         let container = match alignment {
+            ParsedMarkdownTableAlignment::None if cell.is_right_to_left() => v_flex().items_end(),
             ParsedMarkdownTableAlignment::Left | ParsedMarkdownTableAlignment::None => div(),
             ParsedMarkdownTableAlignment::Center => v_flex().items_center(),
             ParsedMarkdownTableAlignment::Right => v_flex().items_end(),
         };
 
         let mut cell = container
-            .w_full()
+            .flex_none()
             .child(contents)
             .px_2()
             .py_1()
             .border_color(cx.border_color);
 
+        if let Some(width) = width {
+            cell = cell.w(width);
+        }
+
         if is_header {
             cell = cell.border_2()
         } else {
@@ -212,14 +618,20 @@ fn render_markdown_table_row(
         items.push(cell);
     }
 
-    h_flex().children(items).into_any_element()
+    div().flex().children(items).into_any_element()
 }
 
 fn render_markdown_block_quote(
     parsed: &ParsedMarkdownBlockQuote,
     cx: &mut RenderContext,
 ) -> AnyElement {
+    // Each level of a nested quote gets a fainter bar, so replies quoted
+    // within replies can be told apart.
+    let mut bar_color = cx.border_color;
+    bar_color.fade_out(0.25 * cx.block_quote_depth.min(3) as f32);
+
     cx.indent += 1;
+    cx.block_quote_depth += 1;
 
     let children: Vec<AnyElement> = parsed
         .children
@@ -227,13 +639,14 @@ fn render_markdown_block_quote(
         .map(|child| render_markdown_block(child, cx))
         .collect();
 
+    cx.block_quote_depth -= 1;
     cx.indent -= 1;
 
     cx.with_common_p(div())
         .child(
             div()
                 .border_l_4()
-                .border_color(cx.border_color)
+                .border_color(bar_color)
                 .pl_3()
                 .children(children),
         )
@@ -244,17 +657,244 @@ fn render_markdown_code_block(
     parsed: &ParsedMarkdownCodeBlock,
     cx: &mut RenderContext,
 ) -> AnyElement {
+    let copy_button = {
+        let contents = parsed.contents.clone();
+        IconButton::new(cx.next_id(&parsed.source_range), IconName::Copy)
+            .icon_size(IconSize::Small)
+            .icon_color(Color::Muted)
+            .tooltip(|cx| Tooltip::text("Copy Code", cx))
+            .on_click(move |_, cx| {
+                cx.write_to_clipboard(ClipboardItem::new(contents.to_string()));
+            })
+    };
+
+    let open_button = cx.workspace.clone().map(|workspace| {
+        let contents = parsed.contents.clone();
+        let language = parsed.language.clone();
+        IconButton::new(cx.next_id(&parsed.source_range), IconName::ExternalLink)
+            .icon_size(IconSize::Small)
+            .icon_color(Color::Muted)
+            .tooltip(|cx| Tooltip::text("Open in New Buffer", cx))
+            .on_click(move |_, cx| {
+                open_code_in_new_buffer(&workspace, &contents, language.clone(), cx);
+            })
+    });
+
+    let toolbar = h_flex()
+        .absolute()
+        .top_1()
+        .right_1()
+        .gap_1()
+        .visible_on_hover("code-block")
+        .children(language_label(parsed).map(|language| {
+            div()
+                .text_ui_sm()
+                .text_color(cx.text_muted_color)
+                .child(language)
+        }))
+        .child(copy_button)
+        .children(open_button);
+
+    let title = parsed.title.clone().map(|title| {
+        div()
+            .px_3()
+            .py_1()
+            .border_b_1()
+            .border_color(cx.border_color)
+            .text_ui_sm()
+            .text_color(cx.text_muted_color)
+            .child(title)
+    });
+
+    let code = if let Some(diagram) = parsed.diagram.clone() {
+        div().p_3().child(
+            img(diagram.data)
+                .w(diagram.size.width)
+                .h(diagram.size.height)
+                .max_w_full(),
+        )
+    } else if cx.code_block_line_numbers {
+        // Each line is laid out in a row with its number, so that the numbers
+        // stay beside their lines when a long line wraps.
+        let lines = code_line_ranges(&parsed.contents);
+        let number_width = lines.len().to_string().len();
+        v_flex()
+            .px_3()
+            .py_3()
+            .font(cx.code_text_style.font_family.clone())
+            .children(lines.into_iter().enumerate().map(|(ix, range)| {
+                h_flex()
+                    .items_start()
+                    .child(
+                        div()
+                            .flex_none()
+                            .pr_3()
+                            .text_color(cx.text_muted_color)
+                            .child(format!("{:>number_width$}", ix + 1)),
+                    )
+                    .child(render_highlighted_code(parsed, range, cx))
+            }))
+    } else {
+        div()
+            .px_3()
+            .py_3()
+            .font(cx.code_text_style.font_family.clone())
+            .child(render_highlighted_code(
+                parsed,
+                0..parsed.contents.len(),
+                cx,
+            ))
+    };
+
     cx.with_common_p(div())
-        .px_3()
-        .py_3()
+        .group("code-block")
+        .relative()
         .bg(cx.code_block_background_color)
-        .child(StyledText::new(parsed.contents.clone()))
+        .children(title)
+        .child(code)
+        .child(toolbar)
         .into_any()
 }
 
+/// The range of each line of code, without its newline.
+fn code_line_ranges(code: &str) -> Vec<Range<usize>> {
+    let code = code.strip_suffix('\n').unwrap_or(code);
+    let mut start = 0;
+    code.split('\n')
+        .map(|line| {
+            let range = start..start + line.len();
+            start = range.end + 1;
+            range
+        })
+        .collect()
+}
+
+/// Renders the code in the given range of the block, with the highlights that fall in it.
+fn render_highlighted_code(
+    parsed: &ParsedMarkdownCodeBlock,
+    range: Range<usize>,
+    cx: &RenderContext,
+) -> StyledText {
+    // An unknown theme is ignored rather than logged, as it'd be logged on every frame.
+    let syntax_theme = parsed
+        .theme
+        .as_deref()
+        .and_then(|name| cx.theme_registry.get(name).ok())
+        .map(|theme| theme.syntax().clone())
+        .unwrap_or_else(|| cx.syntax_theme.clone());
+    let highlights = parsed
+        .highlights
+        .iter()
+        .filter_map(|(highlight_range, highlight_id)| {
+            let start = highlight_range.start.max(range.start);
+            let end = highlight_range.end.min(range.end);
+            if start >= end {
+                return None;
+            }
+            let style = highlight_id.style(&syntax_theme)?;
+            Some((start - range.start..end - range.start, style))
+        });
+
+    let code = if range == (0..parsed.contents.len()) {
+        parsed.contents.clone()
+    } else {
+        SharedString::from(parsed.contents[range.clone()].to_string())
+    };
+    StyledText::new(code).with_highlights(&cx.code_text_style, highlights)
+}
+
+/// The language shown in a code block's toolbar, which is the name of the language
+/// it was highlighted in, falling back to the fence's language when none was loaded.
+fn language_label(parsed: &ParsedMarkdownCodeBlock) -> Option<SharedString> {
+    parsed
+        .language_name
+        .clone()
+        .or_else(|| parsed.language.clone().map(SharedString::from))
+}
+
+/// Opens the contents of a code block in a new, unsaved buffer,
+/// using the fence language, or a file extension, to pick the buffer's language.
+fn open_code_in_new_buffer(
+    workspace: &WeakView<Workspace>,
+    contents: &str,
+    language: Option<String>,
+    cx: &mut WindowContext,
+) {
+    workspace
+        .update(cx, |workspace, cx| {
+            let project = workspace.project().clone();
+            let Some(buffer) = project
+                .update(cx, |project, cx| project.create_buffer(contents, None, cx))
+                .log_err()
+            else {
+                return;
+            };
+
+            if let Some(language) = language {
+                let language = project
+                    .read(cx)
+                    .languages()
+                    .language_for_name_or_extension(&language);
+                let buffer = buffer.clone();
+                cx.spawn(|_, mut cx| async move {
+                    let language = language.await?;
+                    buffer.update(&mut cx, |buffer, cx| {
+                        buffer.set_language(Some(language), cx)
+                    })?;
+                    anyhow::Ok(())
+                })
+                .detach_and_log_err(cx);
+            }
+
+            let editor = cx.new_view(|cx| Editor::for_buffer(buffer, Some(project), cx));
+            workspace.add_item(Box::new(editor), cx);
+        })
+        .log_err();
+}
+
 fn render_markdown_paragraph(parsed: &ParsedMarkdownText, cx: &mut RenderContext) -> AnyElement {
+    // gpui can't align text, so text written right to left is pushed to the right
+    // edge instead. This only aligns paragraphs that fit on one line: once one wraps,
+    // it fills the width of the preview and each of its lines starts at the left.
     cx.with_common_p(div())
-        .child(render_markdown_text(parsed, cx))
+        .when(parsed.is_right_to_left(), |this| this.flex().justify_end())
+        .child(render_markdown_text_with_images(parsed, cx))
+        .into_any_element()
+}
+
+/// Renders text followed by the images in it that have been loaded.
+fn render_markdown_text_with_images(
+    parsed: &ParsedMarkdownText,
+    cx: &mut RenderContext,
+) -> AnyElement {
+    let images = parsed
+        .images
+        .iter()
+        .filter_map(|image| image.loaded.clone())
+        .collect::<Vec<_>>();
+    if images.is_empty() {
+        return render_markdown_text(parsed, cx);
+    }
+
+    // Text of nothing but images, like a row of badges, doesn't need
+    // their alt text unless some of the images couldn't be loaded.
+    let show_text = !parsed.is_only_images() || images.len() < parsed.images.len();
+    let text = show_text.then(|| render_markdown_text(parsed, cx));
+
+    v_flex()
+        .gap_2()
+        .children(text)
+        .child(
+            h_flex()
+                .flex_wrap()
+                .gap_1()
+                .children(images.into_iter().map(|image| {
+                    img(image.data)
+                        .w(image.size.width)
+                        .h(image.size.height)
+                        .max_w_full()
+                })),
+        )
         .into_any_element()
 }
 
@@ -263,25 +903,49 @@ fn render_markdown_text(parsed: &ParsedMarkdownText, cx: &mut RenderContext) ->
 
     let highlights = gpui::combine_highlights(
         parsed.highlights.iter().filter_map(|(range, highlight)| {
-            let highlight = highlight.to_highlight_style(&cx.syntax_theme)?;
-            Some((range.clone(), highlight))
+            let mut style = highlight.to_highlight_style(&cx.syntax_theme)?;
+            if let MarkdownHighlight::Style(markdown_style) = highlight {
+                if markdown_style.highlight {
+                    style.background_color = Some(cx.highlight_background_color);
+                }
+                // Superscripts and subscripts that can't be raised or lowered are muted.
+                if markdown_style.script_fallback {
+                    style.color = Some(cx.text_muted_color);
+                }
+            }
+            Some((range.clone(), style))
         }),
         parsed
             .regions
             .iter()
             .zip(&parsed.region_ranges)
             .filter_map(|(region, range)| {
+                let mut style = HighlightStyle::default();
+
                 if region.code {
-                    Some((
-                        range.clone(),
-                        HighlightStyle {
-                            background_color: Some(cx.code_span_background_color),
-                            ..Default::default()
-                        },
-                    ))
-                } else {
-                    None
+                    style.background_color = Some(cx.code_span_background_color);
                 }
+
+                // Abbreviations are usually marked with a dotted underline, which
+                // can't be drawn, so they get a faint one that sets them apart from links.
+                if region.abbreviation.is_some() {
+                    style.underline = Some(UnderlineStyle {
+                        thickness: px(1.),
+                        color: Some(cx.text_muted_color),
+                        wavy: false,
+                    });
+                }
+
+                if let Some(Link::Broken { .. }) = region.link {
+                    style.color = Some(cx.broken_link_color);
+                    style.underline = Some(UnderlineStyle {
+                        thickness: px(1.),
+                        color: Some(cx.broken_link_color),
+                        wavy: true,
+                    });
+                }
+
+                (style != HighlightStyle::default()).then(|| (range.clone(), style))
             }),
     );
 
@@ -295,14 +959,42 @@ fn render_markdown_text(parsed: &ParsedMarkdownText, cx: &mut RenderContext) ->
     }
 
     let workspace = cx.workspace.clone();
+    let link_handler = cx.link_handler.clone();
+
+    let tooltip_regions: Vec<(Range<usize>, ParsedRegion)> = parsed
+        .region_ranges
+        .iter()
+        .cloned()
+        .zip(parsed.regions.iter().cloned())
+        .filter(|(_, region)| region.link.is_some() || region.abbreviation.is_some())
+        .collect();
 
     InteractiveText::new(
         element_id,
         StyledText::new(parsed.contents.clone()).with_highlights(&cx.text_style, highlights),
     )
-    .on_click(
-        link_ranges,
-        move |clicked_range_ix, window_cx| match &links[clicked_range_ix] {
+    .tooltip(move |ix, cx| {
+        let (_, region) = tooltip_regions
+            .iter()
+            .find(|(range, _)| range.contains(&ix))?;
+        if let Some(expansion) = &region.abbreviation {
+            return Some(Tooltip::text(expansion.clone(), cx));
+        }
+        let link = region.link.as_ref()?;
+        let meta = match link {
+            Link::Heading { section, .. } => return Some(LinkTargetPreview::new(section, cx)),
+            Link::Footnote { text, .. } => return Some(LinkTargetPreview::new(text, cx)),
+            Link::Broken { reason, .. } => reason.message(),
+            _ => "Click to open",
+        };
+        Some(Tooltip::with_meta(link.to_string(), None, meta, cx))
+    })
+    .on_click(link_ranges, move |clicked_range_ix, window_cx| {
+        let link = &links[clicked_range_ix];
+        if let Some(link_handler) = &link_handler {
+            return link_handler(link, window_cx);
+        }
+        match link {
             Link::Web { url } => window_cx.open_url(url),
             Link::Path { path } => {
                 if let Some(workspace) = &workspace {
@@ -311,12 +1003,171 @@ fn render_markdown_text(parsed: &ParsedMarkdownText, cx: &mut RenderContext) ->
                     });
                 }
             }
-        },
-    )
+            Link::Heading { .. } | Link::Footnote { .. } | Link::Broken { .. } => {}
+        }
+    })
     .into_any_element()
 }
 
+/// A popover showing the section of a linked heading, or the text of a footnote,
+/// so that it can be read without scrolling away from the link.
+struct LinkTargetPreview {
+    contents: ParsedMarkdown,
+}
+
+impl LinkTargetPreview {
+    fn new(markdown: &str, cx: &mut WindowContext) -> AnyView {
+        let settings = MarkdownPreviewSettings::get_global(cx);
+        let contents = parse_markdown(
+            markdown,
+            None,
+            &[],
+            settings.smart_typography,
+            settings.hard_line_breaks,
+        );
+        cx.new_view(|_| Self { contents }).into()
+    }
+}
+
+impl Render for LinkTargetPreview {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let blocks = render_parsed_markdown(&self.contents, None, cx);
+        tooltip_container(cx, |container, _| {
+            container
+                .max_w(rems(32.))
+                .max_h(rems(20.))
+                .overflow_hidden()
+                .gap_2()
+                .children(blocks)
+        })
+    }
+}
+
 fn render_markdown_rule(cx: &mut RenderContext) -> AnyElement {
     let rule = div().w_full().h(px(2.)).bg(cx.border_color);
     div().pt_3().pb_3().child(rule).into_any()
 }
+
+/// Lightens or darkens `color`, away from `background`, until the contrast ratio
+/// between them is at least `minimum_contrast`, or `color` is white or black.
+fn with_minimum_contrast(color: Hsla, background: Hsla, minimum_contrast: f32) -> Hsla {
+    let step = if relative_luminance(color) >= relative_luminance(background) {
+        0.02
+    } else {
+        -0.02
+    };
+
+    let mut adjusted = color;
+    while contrast_ratio(adjusted, background) < minimum_contrast {
+        let lightness = (adjusted.l + step).clamp(0., 1.);
+        if lightness == adjusted.l {
+            break;
+        }
+        adjusted.l = lightness;
+    }
+    adjusted
+}
+
+/// The contrast ratio between two colors, as defined by WCAG,
+/// ranging from 1 for identical colors to 21 for black and white.
+fn contrast_ratio(a: Hsla, b: Hsla) -> f32 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn relative_luminance(color: Hsla) -> f32 {
+    let linear = |channel: f32| {
+        if channel <= 0.03928 {
+            channel / 12.92
+        } else {
+            ((channel + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let rgb = color.to_rgb();
+    0.2126 * linear(rgb.r) + 0.7152 * linear(rgb.g) + 0.0722 * linear(rgb.b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_alphabetic_numbering() {
+        assert_eq!(to_alphabetic(1), "a");
+        assert_eq!(to_alphabetic(26), "z");
+        assert_eq!(to_alphabetic(27), "aa");
+        assert_eq!(to_alphabetic(52), "az");
+        assert_eq!(to_alphabetic(703), "aaa");
+        assert_eq!(to_alphabetic(0), "0");
+    }
+
+    #[test]
+    fn test_roman_numbering() {
+        assert_eq!(to_roman(1), "i");
+        assert_eq!(to_roman(4), "iv");
+        assert_eq!(to_roman(9), "ix");
+        assert_eq!(to_roman(14), "xiv");
+        assert_eq!(to_roman(1994), "mcmxciv");
+        assert_eq!(to_roman(3999), "mmmcmxcix");
+        assert_eq!(to_roman(0), "0");
+        assert_eq!(to_roman(4000), "4000");
+        assert_eq!(to_roman(12345), "12345");
+    }
+
+    #[test]
+    fn test_ordered_list_marker_cycles_with_depth() {
+        assert_eq!(ordered_list_marker(3, 1), "3");
+        assert_eq!(ordered_list_marker(3, 2), "c");
+        assert_eq!(ordered_list_marker(3, 3), "iii");
+        assert_eq!(ordered_list_marker(3, 4), "3");
+    }
+
+    #[test]
+    fn test_list_start_carries_into_nested_numbering() {
+        let parsed = parse_markdown(
+            "\
+3. Three
+4. Four
+   2. Nested two
+   3. Nested three
+      5. Innermost five
+",
+            None,
+            &[],
+            false,
+            false,
+        );
+
+        let ParsedMarkdownElement::List(list) = &parsed.children[0] else {
+            panic!("expected a list");
+        };
+        let markers: Vec<String> = list
+            .children
+            .iter()
+            .map(|item| list_item_marker(item, 1))
+            .collect();
+        assert_eq!(markers, vec!["3.", "4."]);
+
+        let ParsedMarkdownElement::List(nested) = list.children[1].contents[1].as_ref() else {
+            panic!("expected a nested list");
+        };
+        let markers: Vec<String> = nested
+            .children
+            .iter()
+            .map(|item| list_item_marker(item, 2))
+            .collect();
+        assert_eq!(markers, vec!["b.", "c."]);
+
+        let ParsedMarkdownElement::List(innermost) = nested.children[1].contents[1].as_ref() else {
+            panic!("expected a doubly nested list");
+        };
+        let markers: Vec<String> = innermost
+            .children
+            .iter()
+            .map(|item| list_item_marker(item, 3))
+            .collect();
+        assert_eq!(markers, vec!["v."]);
+    }
+}
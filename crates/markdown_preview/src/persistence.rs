@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use db::{define_connection, query, sqlez_macros::sql};
+use workspace::{ItemId, WorkspaceDb, WorkspaceId};
+
+define_connection! {
+    // Current schema shape using pseudo-rust syntax:
+    // markdown_previews(
+    //   item_id: usize,
+    //   workspace_id: usize,
+    //   path: PathBuf,
+    //   scroll_top_block: usize,
+    // )
+    pub static ref MARKDOWN_PREVIEW_DB: MarkdownPreviewDb<WorkspaceDb> =
+        &[sql!(
+            CREATE TABLE markdown_previews(
+                item_id INTEGER NOT NULL,
+                workspace_id INTEGER NOT NULL,
+                path BLOB NOT NULL,
+                scroll_top_block INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY(item_id, workspace_id),
+                FOREIGN KEY(workspace_id) REFERENCES workspaces(workspace_id)
+                ON DELETE CASCADE
+                ON UPDATE CASCADE
+            ) STRICT;
+        )];
+}
+
+impl MarkdownPreviewDb {
+    query! {
+        pub fn get_preview(item_id: ItemId, workspace_id: WorkspaceId) -> Result<Option<(PathBuf, u32)>> {
+            SELECT path, scroll_top_block FROM markdown_previews
+            WHERE item_id = ? AND workspace_id = ?
+        }
+    }
+
+    query! {
+        pub async fn save_preview(
+            item_id: ItemId,
+            workspace_id: WorkspaceId,
+            path: PathBuf,
+            scroll_top_block: u32
+        ) -> Result<()> {
+            INSERT INTO markdown_previews
+                (item_id, workspace_id, path, scroll_top_block)
+            VALUES
+                (?1, ?2, ?3, ?4)
+            ON CONFLICT DO UPDATE SET
+                path = ?3,
+                scroll_top_block = ?4
+        }
+    }
+}
@@ -394,6 +394,11 @@ impl ProjectPanel {
             let worktree_id = worktree.id();
             let is_local = project.is_local();
             let is_read_only = project.is_read_only();
+            let is_markdown = entry.is_file()
+                && entry.path.extension().map_or(false, |extension| {
+                    extension == "md" || extension == "markdown"
+                });
+            let abs_path = worktree.abs_path().join(&entry.path);
 
             let context_menu = ContextMenu::build(cx, |menu, cx| {
                 menu.context(self.focus_handle.clone()).when_else(
@@ -441,6 +446,12 @@ impl ProjectPanel {
                             menu.action("Open in Terminal", Box::new(OpenInTerminal))
                                 .action("Search Inside", Box::new(NewSearchInDirectory))
                         })
+                        .when(is_local && is_markdown, |menu| {
+                            menu.action(
+                                "Open Markdown Preview",
+                                Box::new(zed_actions::PreviewFile { path: abs_path }),
+                            )
+                        })
                         .separator()
                         .action("Rename", Box::new(Rename))
                         .when(!is_root, |menu| menu.action("Delete", Box::new(Delete)))
@@ -172,6 +172,8 @@ pub struct Pane {
         Option<Arc<dyn Fn(&mut Pane, &dyn Any, &mut ViewContext<Pane>) -> ControlFlow<(), ()>>>,
     can_split: bool,
     render_tab_bar_buttons: Rc<dyn Fn(&mut Pane, &mut ViewContext<Pane>) -> AnyElement>,
+    tab_context_menu_extensions:
+        Vec<Rc<dyn Fn(ContextMenu, &dyn ItemHandle, &mut WindowContext) -> ContextMenu>>,
     _subscriptions: Vec<Subscription>,
     tab_bar_scroll_handle: ScrollHandle,
     display_nav_history_buttons: bool,
@@ -336,6 +338,7 @@ impl Pane {
                     })
                     .into_any_element()
             }),
+            tab_context_menu_extensions: Vec::new(),
             display_nav_history_buttons: true,
             _subscriptions: subscriptions,
         }
@@ -425,6 +428,16 @@ impl Pane {
         cx.notify();
     }
 
+    /// Registers a callback that can append entries to the context menu
+    /// shown when right-clicking one of this pane's tabs.
+    pub fn add_tab_context_menu_extension<F>(&mut self, cx: &mut ViewContext<Self>, extension: F)
+    where
+        F: 'static + Fn(ContextMenu, &dyn ItemHandle, &mut WindowContext) -> ContextMenu,
+    {
+        self.tab_context_menu_extensions.push(Rc::new(extension));
+        cx.notify();
+    }
+
     pub fn set_custom_drop_handle<F>(&mut self, cx: &mut ViewContext<Self>, handle: F)
     where
         F: 'static + Fn(&mut Pane, &dyn Any, &mut ViewContext<Pane>) -> ControlFlow<(), ()>,
@@ -1382,8 +1395,12 @@ impl Pane {
         };
 
         let pane = cx.view().downgrade();
+        let item = item.boxed_clone();
+        let extensions = self.tab_context_menu_extensions.clone();
         right_click_menu(ix).trigger(tab).menu(move |cx| {
             let pane = pane.clone();
+            let item = item.boxed_clone();
+            let extensions = extensions.clone();
             ContextMenu::build(cx, move |mut menu, cx| {
                 if let Some(pane) = pane.upgrade() {
                     menu = menu
@@ -1454,6 +1471,10 @@ impl Pane {
                             }),
                         );
                     }
+
+                    for extension in &extensions {
+                        menu = extension(menu, item.as_ref(), cx);
+                    }
                 }
 
                 menu
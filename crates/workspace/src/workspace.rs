@@ -463,6 +463,7 @@ pub enum Event {
     PaneAdded(View<Pane>),
     ContactRequestedJoin(u64),
     WorkspaceCreated(WeakView<Workspace>),
+    ActiveItemChanged,
 }
 
 pub enum OpenVisible {
@@ -497,6 +498,8 @@ pub struct Workspace {
     active_call: Option<(Model<ActiveCall>, Vec<Subscription>)>,
     leader_updates_tx: mpsc::UnboundedSender<(PeerId, proto::UpdateFollowers)>,
     database_id: WorkspaceId,
+    /// Whether this workspace is saved to the database, so it can be restored.
+    serializable: bool,
     app_state: Arc<AppState>,
     _subscriptions: Vec<Subscription>,
     _apply_leader_updates: Task<Result<()>>,
@@ -660,7 +663,10 @@ impl Workspace {
 
         let subscriptions = vec![
             cx.observe_window_activation(Self::on_window_activation_changed),
-            cx.observe_window_bounds(move |_, cx| {
+            cx.observe_window_bounds(move |this, cx| {
+                if !this.serializable {
+                    return;
+                }
                 if let Some(display) = cx.display() {
                     // Transform fixed bounds to be stored in terms of the containing display
                     let mut bounds = cx.window_bounds();
@@ -735,6 +741,7 @@ impl Workspace {
             window_edited: false,
             active_call,
             database_id: workspace_id,
+            serializable: true,
             app_state,
             _observe_current_user,
             _apply_leader_updates,
@@ -2217,6 +2224,7 @@ impl Workspace {
             });
             self.active_item_path_changed(cx);
             self.last_active_center_pane = Some(pane.downgrade());
+            cx.emit(Event::ActiveItemChanged);
         }
 
         self.dismiss_zoomed_items_to_reveal(None, cx);
@@ -2250,6 +2258,7 @@ impl Workspace {
                 if &pane == self.active_pane() {
                     self.active_item_path_changed(cx);
                     self.update_active_view_for_followers(cx);
+                    cx.emit(Event::ActiveItemChanged);
                 }
             }
             pane::Event::ChangeItemTitle => {
@@ -3031,9 +3040,11 @@ impl Workspace {
     pub fn on_window_activation_changed(&mut self, cx: &mut ViewContext<Self>) {
         if cx.is_window_active() {
             self.update_active_view_for_followers(cx);
-            cx.background_executor()
-                .spawn(persistence::DB.update_timestamp(self.database_id()))
-                .detach();
+            if self.serializable {
+                cx.background_executor()
+                    .spawn(persistence::DB.update_timestamp(self.database_id()))
+                    .detach();
+            }
         } else {
             for pane in &self.panes {
                 pane.update(cx, |pane, cx| {
@@ -3077,6 +3088,18 @@ impl Workspace {
         self.database_id
     }
 
+    /// Whether this workspace, and the items in it, are saved to the database.
+    pub fn is_serializable(&self) -> bool {
+        self.serializable
+    }
+
+    /// Keeps this workspace out of the database. Used for auxiliary windows that
+    /// show a project that's already open in another window, since saving them
+    /// would replace that window's saved workspace.
+    pub fn disable_serialization(&mut self) {
+        self.serializable = false;
+    }
+
     fn location(&self, cx: &AppContext) -> Option<WorkspaceLocation> {
         let project = self.project().read(cx);
 
@@ -3129,6 +3152,10 @@ impl Workspace {
     }
 
     fn serialize_workspace(&self, cx: &mut WindowContext) {
+        if !self.serializable {
+            return;
+        }
+
         fn serialize_pane_handle(pane_handle: &View<Pane>, cx: &WindowContext) -> SerializedPane {
             let (items, active) = {
                 let pane = pane_handle.read(cx);
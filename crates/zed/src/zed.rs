@@ -357,6 +357,9 @@ fn initialize_pane(workspace: &mut Workspace, pane: &View<Pane>, cx: &mut ViewCo
             toolbar.add_item(quick_action_bar, cx);
             let diagnostic_editor_controls = cx.new_view(|_| diagnostics::ToolbarControls::new());
             toolbar.add_item(diagnostic_editor_controls, cx);
+            let markdown_preview_toolbar =
+                cx.new_view(|_| markdown_preview::MarkdownPreviewToolbar::new());
+            toolbar.add_item(markdown_preview_toolbar, cx);
             let project_search_bar = cx.new_view(|_| ProjectSearchBar::new());
             toolbar.add_item(project_search_bar, cx);
             let lsp_log_item = cx.new_view(|_| language_tools::LspLogToolbarItemView::new());
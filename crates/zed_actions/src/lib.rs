@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use gpui::{actions, impl_actions};
 use serde::Deserialize;
 
@@ -22,4 +24,13 @@ pub struct OpenZedUrl {
 
 impl_actions!(zed, [OpenBrowser, OpenZedUrl]);
 
+/// Renders the Markdown file at the given path in a preview,
+/// without opening an editor for it.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct PreviewFile {
+    pub path: PathBuf,
+}
+
+impl_actions!(markdown, [PreviewFile]);
+
 actions!(zed, [OpenSettings, Quit]);